@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use rmenu::{config, Action, AppContext, Options, Renderer};
+
+// typed one character after the other and deleted again, like a user searching
+static INPUT: &str = "firefox";
+
+fn millis(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1000.
+}
+
+// the value of `--name` in the arguments passed after `cargo bench --`
+fn argument<T: std::str::FromStr>(name: &str, default: T) -> Result<T> {
+  let mut args = std::env::args().skip_while(|arg| arg != name).skip(1);
+  match args.next() {
+    Some(value) => value
+      .parse()
+      .ok()
+      .with_context(|| format!("Invalid value `{}` for {}", value, name)),
+    None => Ok(default),
+  }
+}
+
+// renders the menu with the user config and the binaries in PATH offscreen while typing and
+// moving the selection and reports the frame times, e.g.
+// `cargo bench -- --width 3840 --height 40 --frames 500`
+fn main() -> Result<()> {
+  let width = argument("--width", 3840)?;
+  let height = argument("--height", 40)?;
+  let frames: u32 = argument("--frames", 500)?;
+
+  let mut context = AppContext::new(config::load(Options::default())?)?;
+
+  let entries = context.list().filtered_len();
+  let mut renderer = Renderer::new(&context.config().static_config);
+  let mut canvas = vec![0u8; (width * height * 4) as usize];
+
+  let start = Instant::now();
+  renderer.render(&context, width, height, &mut canvas);
+  let first = start.elapsed();
+
+  let mut times = Vec::with_capacity(frames as usize);
+  let steps = INPUT.chars().count() * 2;
+  for frame in 0..frames as usize {
+    let action = match frame % (steps + 2) {
+      step if step < steps / 2 => {
+        Action::InsertText(INPUT.chars().nth(step).unwrap_or_default().to_string())
+      }
+      step if step < steps => Action::DeleteBackward,
+      step if step == steps => Action::MoveNext,
+      _ => Action::MovePrevious,
+    };
+    context.apply(action);
+
+    let start = Instant::now();
+    renderer.render(&context, width, height, &mut canvas);
+    times.push(start.elapsed());
+  }
+
+  times.sort();
+  let total: Duration = times.iter().sum();
+
+  println!("{} entries on a {}x{} surface", entries, width, height);
+  println!("first frame   {:8.3} ms", millis(first));
+  if !times.is_empty() {
+    println!(
+      "average frame {:8.3} ms over {} frames",
+      millis(total / times.len() as u32),
+      times.len()
+    );
+    println!("median frame  {:8.3} ms", millis(times[times.len() / 2]));
+    println!("slowest frame {:8.3} ms", millis(times[times.len() - 1]));
+  }
+
+  Ok(())
+}
@@ -0,0 +1,235 @@
+use log::{debug, info, warn};
+
+use crate::{
+  command::{self, Command},
+  context::AppContext,
+};
+
+/// What a frontend asks the menu to do, independent of how the user triggered it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+  /// Append typed text to the input
+  InsertText(String),
+  /// Remove the last character of the input
+  DeleteBackward,
+  /// Select the next entry
+  MoveNext,
+  /// Select the previous entry
+  MovePrevious,
+  /// Pick the selected entry
+  Accept,
+  /// Close the menu without picking anything
+  Cancel,
+}
+
+/// A key press as far as the menu is concerned, the frontends translate their key events into it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+  Escape,
+  BackSpace,
+  Tab,
+  /// Shift + Tab
+  LeftTab,
+  Return,
+  /// Any other key producing text
+  Text(String),
+}
+
+impl Action {
+  /// The action a key stands for
+  pub fn from_key(key: Key) -> Option<Self> {
+    match key {
+      Key::Escape => Some(Action::Cancel),
+      Key::BackSpace => Some(Action::DeleteBackward),
+      Key::Tab => Some(Action::MoveNext),
+      Key::LeftTab => Some(Action::MovePrevious),
+      Key::Return => Some(Action::Accept),
+      Key::Text(text) => Some(Action::InsertText(text)),
+    }
+  }
+}
+
+/// The result of an action for the frontend to react on
+#[derive(Debug, Clone)]
+pub enum Outcome {
+  /// Nothing changed, e.g. a move past the end of the list
+  Unchanged,
+  /// The input or the selection changed, so the menu has to be redrawn
+  Changed,
+  /// The selected entry was picked, the frontend decides to launch it
+  Accepted(Command),
+  /// Accepted while no entry matched the input
+  NoMatch,
+  /// The input of a password prompt was accepted
+  Submitted(String),
+  /// The menu was closed without picking anything
+  Cancelled,
+}
+
+impl AppContext {
+  /// Changes the state of the menu and tells the frontend what to do about it
+  pub fn apply(&mut self, action: Action) -> Outcome {
+    match &action {
+      // the typed text of a password must not end up in the logs
+      Action::InsertText(_) if self.config.static_config.password() => {
+        debug!("Action InsertText(..)")
+      }
+      action => debug!("Action {:?}", action),
+    }
+    match action {
+      Action::InsertText(text) => {
+        if text.is_empty() {
+          return Outcome::Unchanged;
+        }
+        // append and apply the filter, the best match gets selected
+        self.append_and_filter(&text);
+        self.current_index = 0;
+        Outcome::Changed
+      }
+      Action::DeleteBackward => {
+        if self.input.is_empty() {
+          return Outcome::Unchanged;
+        }
+        self.pop_and_filter();
+        Outcome::Changed
+      }
+      Action::MoveNext => {
+        if self.current_index + 1 < self.list.filtered_len() {
+          self.current_index += 1;
+          Outcome::Changed
+        } else {
+          Outcome::Unchanged
+        }
+      }
+      Action::MovePrevious => {
+        if self.current_index > 0 {
+          self.current_index -= 1;
+          Outcome::Changed
+        } else {
+          Outcome::Unchanged
+        }
+      }
+      Action::Accept if self.config.static_config.password() => {
+        Outcome::Submitted(self.input.clone())
+      }
+      Action::Accept => match self.target() {
+        Some(target) => Outcome::Accepted(target.clone()),
+        None => Outcome::NoMatch,
+      },
+      Action::Cancel => Outcome::Cancelled,
+    }
+  }
+
+  /// Spawns the command and counts it in the usage statistic, returns the exit code for rmenu
+  pub fn launch(&mut self, command: &Command) -> i32 {
+    info!("Execute {}", command);
+    let code = command::launch(command);
+
+    if let Err(e) = self.config.increment_and_store_history(command.binary()) {
+      warn!("Failed to store history data - {}", e);
+    }
+
+    code
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::{
+    command::CommandList,
+    config::{AppConfig, StaticConfig},
+  };
+
+  fn context(entries: &[&str]) -> AppContext {
+    let config = AppConfig::from(StaticConfig::default());
+    let list = CommandList::from_paths(&config, entries.iter().map(PathBuf::from).collect());
+    AppContext::with_list(config, list)
+  }
+
+  fn names(context: &AppContext) -> Vec<&str> {
+    context
+      .list()
+      .filtered()
+      .iter()
+      .map(|command| command.name.as_str())
+      .collect()
+  }
+
+  #[test]
+  fn insert_text_filters_and_resets_the_index() {
+    let mut context = context(&["/bin/firefox", "/bin/fish", "/bin/vim"]);
+    assert!(matches!(context.apply(Action::MoveNext), Outcome::Changed));
+    assert_eq!(context.current_index(), 1);
+
+    assert!(matches!(
+      context.apply(Action::InsertText(String::from("fi"))),
+      Outcome::Changed
+    ));
+    assert_eq!(context.input(), "fi");
+    assert_eq!(context.current_index(), 0);
+    assert_eq!(names(&context), ["firefox", "fish"]);
+  }
+
+  #[test]
+  fn delete_backward_on_empty_input_is_unchanged() {
+    let mut context = context(&["/bin/firefox"]);
+    assert!(matches!(
+      context.apply(Action::DeleteBackward),
+      Outcome::Unchanged
+    ));
+
+    context.apply(Action::InsertText(String::from("x")));
+    assert!(names(&context).is_empty());
+    assert!(matches!(
+      context.apply(Action::DeleteBackward),
+      Outcome::Changed
+    ));
+    assert_eq!(context.input(), "");
+    assert_eq!(names(&context), ["firefox"]);
+  }
+
+  #[test]
+  fn moves_stay_within_the_list() {
+    let mut context = context(&["/bin/a", "/bin/b"]);
+    assert!(matches!(
+      context.apply(Action::MovePrevious),
+      Outcome::Unchanged
+    ));
+    assert_eq!(context.current_index(), 0);
+
+    assert!(matches!(context.apply(Action::MoveNext), Outcome::Changed));
+    assert!(matches!(
+      context.apply(Action::MoveNext),
+      Outcome::Unchanged
+    ));
+    assert_eq!(context.current_index(), 1);
+
+    assert!(matches!(
+      context.apply(Action::MovePrevious),
+      Outcome::Changed
+    ));
+    assert_eq!(context.current_index(), 0);
+  }
+
+  #[test]
+  fn accept_picks_the_selection_or_no_match() {
+    let mut context = context(&["/bin/a", "/bin/b"]);
+    context.apply(Action::MoveNext);
+    match context.apply(Action::Accept) {
+      Outcome::Accepted(command) => assert_eq!(command.name, "b"),
+      _ => panic!("Expected the selection to be accepted"),
+    }
+
+    context.apply(Action::InsertText(String::from("x")));
+    assert!(matches!(context.apply(Action::Accept), Outcome::NoMatch));
+  }
+
+  #[test]
+  fn cancel_closes_the_menu() {
+    let mut context = context(&["/bin/a"]);
+    assert!(matches!(context.apply(Action::Cancel), Outcome::Cancelled));
+  }
+}
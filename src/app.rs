@@ -1,74 +1,70 @@
+use anyhow::Context;
 use log::info;
 
-use smithay_client_toolkit::seat::keyboard;
+use rmenu::{config, AppContext, Outcome};
 use wayland_client::{globals::registry_queue_init, Connection};
 
-use crate::{
-  command,
-  config::{self, AppConfig},
-  menu,
-};
-
-pub struct AppContext {
-  pub config: AppConfig,
-  pub input: String,
-  pub list: command::CommandList,
-  pub current_index: usize,
-  pub modifiers: keyboard::Modifiers,
-}
+use crate::menu;
 
 pub struct App {}
 
-impl AppContext {
-  pub fn target(&self) -> Option<&command::Command> {
-    self.list.filtered.get(self.current_index)
-  }
-  fn filter(&mut self) {
-      self.list.filter(&self.input, &self.config.history);
-      info!("{}", self.list);
-  }
-  pub fn pop_and_filter(&mut self) {
-    self.input.pop();
-    self.filter()
-  }
-  pub fn append_and_filter(&mut self, input: &str) {
-    self.input.push_str(input);
-    self.filter()
-  }
-}
-
 impl App {
   pub fn new() -> App {
     App {}
   }
-  pub fn run(&mut self, app_config: config::AppConfig) -> anyhow::Result<()> {
+  // shows the menu until it is closed and returns the exit code
+  pub fn run(&mut self, app_config: config::AppConfig) -> anyhow::Result<i32> {
     info!("Config {:?}", app_config);
 
-    let app_context = AppContext {
-      input: String::new(),
-      list: command::CommandList::new(&app_config)?,
-      modifiers: Default::default(),
-      current_index: 0,
-      config: app_config,
-    };
+    let app_context = AppContext::new(app_config)?;
+
+    info!("{}", app_context.list());
 
-    info!("{}", app_context.list);
+    let (mut app_context, outcome) = self.show(app_context);
 
+    Ok(match outcome? {
+      Outcome::Accepted(command) => app_context.launch(&command),
+      Outcome::Submitted(input) => {
+        println!("{}", input);
+        0
+      }
+      // exit with failure (no target)
+      Outcome::NoMatch => 10,
+      // like dmenu, so a script piping the password on does not go on without one
+      Outcome::Cancelled if app_context.config().static_config.password() => 1,
+      Outcome::Cancelled | Outcome::Unchanged | Outcome::Changed => 0,
+    })
+  }
+  // shows the menu until it is closed and returns its state along with what closed it, the state
+  // comes back even if the menu failed so it can be shown again
+  pub fn show(&mut self, app_context: AppContext) -> (AppContext, anyhow::Result<Outcome>) {
     // All Wayland apps start by connecting the compositor (server).
-    let conn = Connection::connect_to_env().expect("Failed to connect to compositor");
+    let conn = match Connection::connect_to_env().context("Failed to connect to the compositor") {
+      Ok(conn) => conn,
+      Err(e) => return (app_context, Err(e)),
+    };
 
     // Enumerate the list of globals to get the protocols the server implements.
     let (globals, mut event_queue) =
-      registry_queue_init(&conn).expect("Failed to initialize queue");
+      match registry_queue_init(&conn).context("Failed to initialize the queue") {
+        Ok(registry) => registry,
+        Err(e) => return (app_context, Err(e)),
+      };
     let qh = event_queue.handle();
 
-    let mut menu_shell = menu::Shell::new(app_context, globals, qh);
+    let mut menu_shell = match menu::Shell::new(app_context, globals, qh) {
+      Ok(menu_shell) => menu_shell,
+      Err((app_context, e)) => return (app_context, Err(e)),
+    };
 
     // Run the loop until exit
     while !menu_shell.about_to_exit() {
-      event_queue.blocking_dispatch(&mut menu_shell)?;
+      if let Err(e) = event_queue.blocking_dispatch(&mut menu_shell) {
+        return (menu_shell.finish().0, Err(e.into()));
+      }
     }
 
-    Ok(())
+    let (app_context, outcome) = menu_shell.finish();
+    (app_context, Ok(outcome))
   }
 }
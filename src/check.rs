@@ -0,0 +1,497 @@
+use std::{
+  collections::HashMap,
+  fmt::Display,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use yaml_rust2::{
+  parser::{Event, MarkedEventReceiver, Parser},
+  scanner::Marker,
+};
+
+use crate::{
+  config::{Font, Options, StaticConfig, Style, Theme},
+  icon,
+  text::FontFace,
+  theme,
+};
+
+// the kinds of files that can be checked
+trait Checked: DeserializeOwned {
+  fn font(&self) -> Option<&Font>;
+  fn style(&self) -> Option<&Style>;
+  fn theme(&self) -> Option<&str> {
+    None
+  }
+}
+
+impl Checked for StaticConfig {
+  fn font(&self) -> Option<&Font> {
+    self.font.as_ref()
+  }
+  fn style(&self) -> Option<&Style> {
+    self.style.as_ref()
+  }
+  fn theme(&self) -> Option<&str> {
+    self.theme.as_deref()
+  }
+}
+
+impl Checked for Theme {
+  fn font(&self) -> Option<&Font> {
+    self.font.as_ref()
+  }
+  fn style(&self) -> Option<&Style> {
+    self.style.as_ref()
+  }
+}
+
+/// A problem found in a config or theme file
+pub struct Diagnostic {
+  pub file: PathBuf,
+  /// The line and the column
+  pub location: Option<(usize, usize)>,
+  pub message: String,
+}
+
+/// The problems found in the config files and the theme they use
+pub struct Report {
+  /// The files checked, the config files first and the theme last
+  pub checked: Vec<PathBuf>,
+  pub diagnostics: Vec<Diagnostic>,
+  /// The problems of the options, like an unknown theme
+  pub errors: Vec<String>,
+}
+
+impl Report {
+  /// Whether no problem was found
+  pub fn is_ok(&self) -> bool {
+    self.diagnostics.is_empty() && self.errors.is_empty()
+  }
+}
+
+enum Frame {
+  Mapping { path: String, key: Option<String> },
+  Sequence { path: String, index: usize },
+}
+
+// collects the position of every mapping key and sequence item by its dotted path (e.g. `style.height`)
+#[derive(Default)]
+struct KeyLocations {
+  stack: Vec<Frame>,
+  keys: HashMap<String, Marker>,
+}
+
+impl KeyLocations {
+  fn get(&self, path: &str) -> Option<(usize, usize)> {
+    self.keys.get(path).map(|m| (m.line(), m.col() + 1))
+  }
+
+  // path of the next value node in the current collection
+  fn next_node(&mut self) -> String {
+    match self.stack.last_mut() {
+      Some(Frame::Mapping { key, .. }) => key.take().unwrap_or_default(),
+      Some(Frame::Sequence { path, index }) => {
+        let node = join(path, &index.to_string());
+        *index += 1;
+        node
+      }
+      None => String::new(),
+    }
+  }
+}
+
+impl MarkedEventReceiver for KeyLocations {
+  fn on_event(&mut self, ev: Event, mark: Marker) {
+    match ev {
+      Event::Scalar(value, ..) => {
+        if let Some(Frame::Mapping { path, key: key @ None }) = self.stack.last_mut() {
+          let node = join(path, &value);
+          self.keys.entry(node.clone()).or_insert(mark);
+          *key = Some(node);
+        } else {
+          // values keep the position of their key, sequence items get their own
+          let node = self.next_node();
+          self.keys.entry(node).or_insert(mark);
+        }
+      }
+      Event::Alias(..) => {
+        self.next_node();
+      }
+      Event::MappingStart(..) => {
+        let path = self.next_node();
+        self.stack.push(Frame::Mapping { path, key: None });
+      }
+      Event::SequenceStart(..) => {
+        let path = self.next_node();
+        self.stack.push(Frame::Sequence { path, index: 0 });
+      }
+      Event::MappingEnd | Event::SequenceEnd => {
+        self.stack.pop();
+      }
+      _ => {}
+    }
+  }
+}
+
+impl Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.location {
+      Some((line, column)) => write!(
+        f,
+        "{}:{}:{}: {}",
+        self.file.display(),
+        line,
+        column,
+        self.message
+      ),
+      None => write!(f, "{}: {}", self.file.display(), self.message),
+    }
+  }
+}
+
+fn join(parent: &str, key: &str) -> String {
+  if parent.is_empty() {
+    String::from(key)
+  } else {
+    format!("{}.{}", parent, key)
+  }
+}
+
+fn ignored_path(path: &serde_ignored::Path) -> String {
+  match path {
+    serde_ignored::Path::Root => String::new(),
+    serde_ignored::Path::Seq { parent, index } => join(&ignored_path(parent), &index.to_string()),
+    serde_ignored::Path::Map { parent, key } => join(&ignored_path(parent), key),
+    serde_ignored::Path::Some { parent }
+    | serde_ignored::Path::NewtypeStruct { parent }
+    | serde_ignored::Path::NewtypeVariant { parent } => ignored_path(parent),
+  }
+}
+
+// splits the path serde_yaml puts in front of the message off as a dotted path, e.g.
+// `style.elements.1` for `style.elements[1]: unknown variant`, None for the whole file
+fn error_path(message: &str) -> Option<(String, &str)> {
+  let (path, problem) = message.split_once(": ")?;
+  path
+    .chars()
+    .all(|c| c.is_alphanumeric() || "_-.[]".contains(c))
+    .then(|| (path.replace('[', ".").replace(']', ""), problem))
+}
+
+// the dotted path the way serde_yaml shows it, with the sequence indices in brackets
+fn display_path(path: &str) -> String {
+  path.split('.').fold(String::new(), |shown, part| {
+    match (shown.is_empty(), part.parse::<usize>()) {
+      (false, Ok(index)) => format!("{}[{}]", shown, index),
+      _ => join(&shown, part),
+    }
+  })
+}
+
+// the path in the file of a value in the text left after the values at the `removed` paths were
+// taken out, the items of a sequence after a removed one moved up
+fn original_path(path: &str, removed: &[String]) -> String {
+  path.split('.').fold(String::new(), |original, part| {
+    let Ok(mut index) = part.parse::<usize>() else {
+      return join(&original, part);
+    };
+    let mut before: Vec<usize> = removed
+      .iter()
+      .filter_map(|removed| {
+        let (parent, item) = removed.rsplit_once('.').unwrap_or(("", removed));
+        (parent == original)
+          .then(|| item.parse::<usize>().ok())
+          .flatten()
+      })
+      .collect();
+    before.sort_unstable();
+    for removed in before {
+      if removed <= index {
+        index += 1;
+      }
+    }
+    join(&original, &index.to_string())
+  })
+}
+
+// removes the value at the dotted path, false if there is none
+fn remove(value: &mut Value, path: &str) -> bool {
+  let (parent, key) = match path.rsplit_once('.') {
+    Some((parent, key)) => (Some(parent), key),
+    None => (None, path),
+  };
+  let mut node = value;
+  for part in parent.into_iter().flat_map(|parent| parent.split('.')) {
+    node = match node {
+      Value::Mapping(mapping) => match mapping.get_mut(part) {
+        Some(child) => child,
+        None => return false,
+      },
+      Value::Sequence(sequence) => {
+        match part.parse::<usize>().ok().and_then(|i| sequence.get_mut(i)) {
+          Some(child) => child,
+          None => return false,
+        }
+      }
+      _ => return false,
+    };
+  }
+  match node {
+    Value::Mapping(mapping) => mapping.remove(key).is_some(),
+    Value::Sequence(sequence) => match key.parse::<usize>() {
+      Ok(index) if index < sequence.len() => {
+        sequence.remove(index);
+        true
+      }
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
+fn check_file<T: Checked>(file: &Path) -> Result<(Option<T>, Vec<Diagnostic>)> {
+  let source = std::fs::read_to_string(file)
+    .with_context(|| format!("Failed to read config {}", file.display()))?;
+
+  let diagnostic = |location: Option<(usize, usize)>, message: String| Diagnostic {
+    file: file.to_path_buf(),
+    location,
+    message,
+  };
+
+  // syntax errors are reported by serde_yaml below, so only the positions are of interest here
+  let mut locations = KeyLocations::default();
+  let _ = Parser::new_from_str(&source).load(&mut locations, false);
+
+  let mut diagnostics = Vec::new();
+  let mut unknown: Vec<String> = Vec::new();
+
+  // an invalid value is reported and left out to check the rest of the file, until it
+  // deserializes or the problem cannot be narrowed down to a single value
+  let mut value: Option<Value> = serde_yaml::from_str(&source).ok();
+  let mut text = source.clone();
+  // the original paths of the values left out so far
+  let mut removed: Vec<String> = Vec::new();
+  let config = loop {
+    let result: Result<T, _> =
+      serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&text), |path| {
+        let key = original_path(&ignored_path(&path), &removed);
+        if !unknown.contains(&key) {
+          unknown.push(key);
+        }
+      });
+    let e = match result {
+      Ok(config) => break Some(config),
+      Err(e) => e,
+    };
+
+    let location = e.location().map(|l| (l.line(), l.column()));
+    // the location is already part of the diagnostic
+    let message = match location {
+      Some((line, column)) => e
+        .to_string()
+        .replace(&format!(" at line {} column {}", line, column), ""),
+      None => e.to_string(),
+    };
+    let Some((path, problem)) = error_path(&message) else {
+      diagnostics.push(diagnostic(location.filter(|_| removed.is_empty()), message));
+      break None;
+    };
+    let original = original_path(&path, &removed);
+    // the values left out before moved the others, so the rest is located by the key
+    let location = match removed.is_empty() {
+      true => location,
+      false => locations.get(&original),
+    };
+    // a required value missing only because it was left out before is not reported again
+    let left_out = problem
+      .strip_prefix("missing field `")
+      .and_then(|field| field.strip_suffix('`'))
+      .is_some_and(|field| removed.contains(&join(&original, field)));
+    if !left_out {
+      diagnostics.push(diagnostic(
+        location,
+        format!("{}: {}", display_path(&original), problem),
+      ));
+    }
+
+    match value.as_mut() {
+      Some(value) => {
+        if !remove(value, &path) {
+          break None;
+        }
+        text = serde_yaml::to_string(value)?;
+      }
+      None => break None,
+    }
+    removed.push(original);
+  };
+
+  diagnostics.extend(
+    unknown
+      .into_iter()
+      .map(|key| diagnostic(locations.get(&key), format!("unknown key `{}`", key))),
+  );
+
+  let Some(config) = config else {
+    diagnostics.sort_by_key(|d| d.location);
+    return Ok((None, diagnostics));
+  };
+
+  if let Some(name) = config.theme() {
+    if !theme::is_valid_name(name) {
+      diagnostics.push(diagnostic(
+        locations.get("theme"),
+        format!("invalid theme name `{}`, it must not contain a path", name),
+      ));
+    } else if theme::user_theme_path(name).is_none() && theme::builtin_theme(name).is_none() {
+      diagnostics.push(diagnostic(
+        locations.get("theme"),
+        format!("unknown theme `{}`", name),
+      ));
+    }
+  }
+
+  if let Some(font) = config.font() {
+    if let Some(path) = font.path.as_ref() {
+      if !path.exists() {
+        diagnostics.push(diagnostic(
+          locations.get("font.path"),
+          format!("font file `{}` does not exist", path.display()),
+        ));
+      } else if FontFace::from_path(path).is_none() {
+        diagnostics.push(diagnostic(
+          locations.get("font.path"),
+          format!("font file `{}` could not be loaded", path.display()),
+        ));
+      }
+    }
+
+    if let Some(name) = font.name.as_ref() {
+      if FontFace::by_name(name).is_none() {
+        diagnostics.push(diagnostic(
+          locations.get("font.name"),
+          format!("no installed font with the PostScript name `{}`", name),
+        ));
+      }
+    }
+
+    for (index, spec) in font.fallback.iter().flatten().enumerate() {
+      if FontFace::from_spec(spec).is_none() {
+        diagnostics.push(diagnostic(
+          locations.get(&format!("font.fallback.{}", index)),
+          format!("fallback font `{}` could not be loaded", spec),
+        ));
+      }
+    }
+  }
+
+  if let Some(style) = config.style() {
+    if let Some(gradient) = style.background_gradient.as_ref() {
+      if gradient.stops.is_empty() {
+        diagnostics.push(diagnostic(
+          locations.get("style.background_gradient.stops"),
+          String::from("a gradient needs at least one color"),
+        ));
+      }
+    }
+
+    if let Some(image) = style.background_image.as_ref() {
+      if !image.path.exists() {
+        diagnostics.push(diagnostic(
+          locations.get("style.background_image.path"),
+          format!("background image `{}` does not exist", image.path.display()),
+        ));
+      } else if icon::load_png(&image.path).is_none() {
+        diagnostics.push(diagnostic(
+          locations.get("style.background_image.path"),
+          format!(
+            "background image `{}` could not be loaded as PNG",
+            image.path.display()
+          ),
+        ));
+      }
+    }
+  }
+
+  diagnostics.sort_by_key(|d| d.location);
+
+  Ok((Some(config), diagnostics))
+}
+
+impl Options {
+  /// Checks the config files and the theme they use and reports every problem found instead of
+  /// stopping at the first one
+  pub fn check(&self) -> Result<Report> {
+    let mut report = Report {
+      checked: Vec::new(),
+      diagnostics: Vec::new(),
+      errors: Vec::new(),
+    };
+
+    // the theme of the layer with the highest precedence is used
+    let mut layer_theme = None;
+    for file in self.config_layers() {
+      let (config, found) = check_file::<StaticConfig>(&file)?;
+      layer_theme = config.and_then(|c| c.theme).or(layer_theme);
+      report.diagnostics.extend(found);
+      report.checked.push(file);
+    }
+
+    if let Some(name) = self.theme.clone().or(layer_theme) {
+      if let Some(file) = theme::user_theme_path(&name) {
+        let (_, found) = check_file::<Theme>(&file)?;
+        report.diagnostics.extend(found);
+        report.checked.push(file);
+      } else if self.theme.is_some() {
+        if !theme::is_valid_name(&name) {
+          report.errors.push(format!(
+            "Invalid theme name `{}`, it must not contain a path",
+            name
+          ));
+        } else if theme::builtin_theme(&name).is_none() {
+          report.errors.push(format!("Unknown theme `{}`", name));
+        }
+      }
+    }
+
+    Ok(report)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_every_invalid_value() {
+    let file = std::env::temp_dir().join(format!("rmenu-check-{}.yaml", std::process::id()));
+    std::fs::write(
+      &file,
+      "style:\n  foreground_color: \"#GGG\"\n  elements: [prompt, nope, list, bad]\nbogus: 1\nprompt: [1]\n",
+    )
+    .unwrap();
+    let (config, diagnostics) = check_file::<StaticConfig>(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+
+    assert!(config.is_some());
+    let found: Vec<_> = diagnostics
+      .iter()
+      .map(|d| (d.location, d.message.split(':').next().unwrap()))
+      .collect();
+    assert_eq!(
+      found,
+      [
+        (Some((2, 21)), "style.foreground_color"),
+        (Some((3, 22)), "style.elements[1]"),
+        (Some((3, 34)), "style.elements[3]"),
+        (Some((4, 1)), "unknown key `bogus`"),
+        (Some((5, 1)), "prompt"),
+      ]
+    );
+  }
+}
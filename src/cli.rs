@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use serde_yaml::Value;
+
+use rmenu::config::{override_value, Color, Options, Position};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+  #[clap(short, long)]
+  pub config: Option<PathBuf>,
+  #[clap(long)]
+  pub history: Option<PathBuf>,
+  #[clap(long)]
+  pub theme: Option<String>,
+  /// Override a single config key, e.g. `--set style.height=40`
+  #[clap(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+  pub overrides: Vec<Value>,
+  /// Prompt shown in front of the input
+  #[clap(short, long)]
+  pub prompt: Option<String>,
+  /// Match the entries case insensitively, the default unless `ignore_case: false` is set
+  #[clap(short = 'i', long)]
+  pub ignore_case: bool,
+  /// Show the menu at the bottom of the screen
+  #[clap(short, long)]
+  pub bottom: bool,
+  /// List the entries vertically in the given number of lines
+  #[clap(short, long)]
+  pub lines: Option<u32>,
+  /// Font name with an optional size, e.g. `monospace:size=12` (dmenu `-fn`)
+  #[clap(long = "fn", value_name = "FONT")]
+  pub font: Option<String>,
+  /// Normal background color (dmenu `-nb`)
+  #[clap(long = "nb", value_name = "COLOR")]
+  pub normal_background: Option<Color>,
+  /// Normal foreground color (dmenu `-nf`)
+  #[clap(long = "nf", value_name = "COLOR")]
+  pub normal_foreground: Option<Color>,
+  /// Selected background color (dmenu `-sb`)
+  #[clap(long = "sb", value_name = "COLOR")]
+  pub selected_background: Option<Color>,
+  /// Selected foreground color (dmenu `-sf`)
+  #[clap(long = "sf", value_name = "COLOR")]
+  pub selected_foreground: Option<Color>,
+  /// Ask for a password, the input is hidden and printed to stdout
+  #[clap(long)]
+  pub password: bool,
+  /// Type the keys, e.g. `fir<Tab><Return>`, without a compositor and print the result as JSON
+  #[clap(long, value_name = "KEYS", conflicts_with = "replay_file")]
+  pub replay: Option<String>,
+  /// Read the keys to replay from a file
+  #[clap(long, value_name = "FILE")]
+  pub replay_file: Option<PathBuf>,
+  /// File with one entry per line to replay the keys on instead of the binaries in PATH
+  #[clap(long, value_name = "FILE")]
+  pub replay_entries: Option<PathBuf>,
+  #[clap(subcommand)]
+  pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+  /// Validate the config file and report every problem found
+  CheckConfig,
+  /// Render the menu into a PNG file without a compositor
+  Render {
+    /// Width of the image in pixels
+    #[arg(long, default_value_t = 1920)]
+    width: i32,
+    /// Height of the image in pixels, defaults to the height of the menu
+    #[arg(long)]
+    height: Option<i32>,
+    /// Text typed into the input
+    #[arg(long, default_value = "")]
+    input: String,
+    /// Index of the selected entry
+    #[arg(long, default_value_t = 0)]
+    selected: usize,
+    /// File with one entry per line instead of the built-in sample entries
+    #[arg(long)]
+    entries: Option<PathBuf>,
+    /// The PNG file to write
+    #[arg(long)]
+    output: PathBuf,
+  },
+  /// Act as a pinentry for GnuPG speaking the Assuan protocol on stdin and stdout
+  Pinentry {
+    /// The options passed by gpg-agent, the display is taken from the environment instead
+    #[arg(hide = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    ignored: Vec<String>,
+  },
+}
+
+impl Args {
+  // the options of the config with the dmenu compatible flags and `--password` as overrides in
+  // front of the ones given by `--set`
+  pub fn options(&self) -> Result<Options> {
+    let mut overrides = vec![];
+
+    if let Some(prompt) = &self.prompt {
+      overrides.push(override_value("prompt", Value::String(prompt.clone()))?);
+    }
+    if self.ignore_case {
+      overrides.push(override_value("ignore_case", Value::Bool(true))?);
+    }
+    if self.password {
+      overrides.push(override_value("password", Value::Bool(true))?);
+    }
+    if self.bottom {
+      overrides.push(override_value(
+        "style.position",
+        serde_yaml::to_value(Position::Bottom)?,
+      )?);
+    }
+    if let Some(lines) = self.lines {
+      overrides.push(override_value("style.lines", Value::from(lines))?);
+    }
+    if let Some(font) = &self.font {
+      let (name, size) = parse_font_pattern(font);
+      overrides.push(override_value("font.name", Value::String(name))?);
+      if let Some(size) = size {
+        overrides.push(override_value("font.size", Value::from(size))?);
+      }
+    }
+
+    for (key, color) in [
+      ("style.background_color", self.normal_background),
+      ("style.foreground_color", self.normal_foreground),
+      ("style.selected_background_color", self.selected_background),
+      ("style.highlight_color", self.selected_foreground),
+    ] {
+      if let Some(color) = color {
+        overrides.push(override_value(key, serde_yaml::to_value(color)?)?);
+      }
+    }
+
+    overrides.extend(self.overrides.iter().cloned());
+
+    Ok(Options {
+      config: self.config.clone(),
+      history: self.history.clone(),
+      theme: self.theme.clone(),
+      overrides,
+    })
+  }
+}
+
+// dmenu takes multi character flags with a single dash, clap needs them with two
+pub fn normalize_args(args: impl Iterator<Item = String>) -> Vec<String> {
+  let mut args: Vec<String> = args
+    .map(|arg| match arg.as_str() {
+      "-fn" | "-nb" | "-nf" | "-sb" | "-sf" => format!("-{}", arg),
+      _ => arg,
+    })
+    .collect();
+
+  // started through a `pinentry-rmenu` link, the pinentry options of gpg-agent follow
+  let program = args.first().map(Path::new).and_then(Path::file_name);
+  if program.is_some_and(|name| name.to_string_lossy().starts_with("pinentry")) {
+    args.insert(1, String::from("pinentry"));
+  }
+
+  args
+}
+
+// splits a fontconfig/Xft like pattern as used by dmenu (`DejaVu Sans Mono:size=12` or
+// `monospace-12`) into the name and the size in pixels
+fn parse_font_pattern(pattern: &str) -> (String, Option<f32>) {
+  let points_to_pixels = |points: f32| points * 96. / 72.;
+  let mut parts = pattern.split(':');
+  let mut name = parts.next().unwrap_or_default().trim().to_string();
+  let mut size = None;
+
+  for part in parts {
+    if let Some(points) = part.strip_prefix("size=") {
+      size = points.parse::<f32>().ok().map(points_to_pixels);
+    } else if let Some(pixels) = part.strip_prefix("pixelsize=") {
+      size = pixels.parse::<f32>().ok();
+    }
+  }
+
+  if size.is_none() {
+    if let Some((prefix, points)) = name.rsplit_once('-') {
+      if let Ok(points) = points.parse::<f32>() {
+        size = Some(points_to_pixels(points));
+        name = prefix.to_string();
+      }
+    }
+  }
+
+  (name, size)
+}
+
+// parses `--set style.height=40` into a nested mapping, the value is read as YAML
+fn parse_override(arg: &str) -> Result<Value> {
+  let (key, value) = arg
+    .split_once('=')
+    .ok_or_else(|| anyhow!("expected KEY=VALUE"))?;
+
+  let value = if value.is_empty() {
+    Value::String(String::new())
+  } else {
+    serde_yaml::from_str(value)?
+  };
+
+  override_value(key, value)
+}
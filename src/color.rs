@@ -0,0 +1,359 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::Color;
+
+// CSS named colors, sorted by name for the binary search
+static NAMED_COLORS: [(&str, u32); 148] = [
+  ("aliceblue", 0xF0F8FF),
+  ("antiquewhite", 0xFAEBD7),
+  ("aqua", 0x00FFFF),
+  ("aquamarine", 0x7FFFD4),
+  ("azure", 0xF0FFFF),
+  ("beige", 0xF5F5DC),
+  ("bisque", 0xFFE4C4),
+  ("black", 0x000000),
+  ("blanchedalmond", 0xFFEBCD),
+  ("blue", 0x0000FF),
+  ("blueviolet", 0x8A2BE2),
+  ("brown", 0xA52A2A),
+  ("burlywood", 0xDEB887),
+  ("cadetblue", 0x5F9EA0),
+  ("chartreuse", 0x7FFF00),
+  ("chocolate", 0xD2691E),
+  ("coral", 0xFF7F50),
+  ("cornflowerblue", 0x6495ED),
+  ("cornsilk", 0xFFF8DC),
+  ("crimson", 0xDC143C),
+  ("cyan", 0x00FFFF),
+  ("darkblue", 0x00008B),
+  ("darkcyan", 0x008B8B),
+  ("darkgoldenrod", 0xB8860B),
+  ("darkgray", 0xA9A9A9),
+  ("darkgreen", 0x006400),
+  ("darkgrey", 0xA9A9A9),
+  ("darkkhaki", 0xBDB76B),
+  ("darkmagenta", 0x8B008B),
+  ("darkolivegreen", 0x556B2F),
+  ("darkorange", 0xFF8C00),
+  ("darkorchid", 0x9932CC),
+  ("darkred", 0x8B0000),
+  ("darksalmon", 0xE9967A),
+  ("darkseagreen", 0x8FBC8F),
+  ("darkslateblue", 0x483D8B),
+  ("darkslategray", 0x2F4F4F),
+  ("darkslategrey", 0x2F4F4F),
+  ("darkturquoise", 0x00CED1),
+  ("darkviolet", 0x9400D3),
+  ("deeppink", 0xFF1493),
+  ("deepskyblue", 0x00BFFF),
+  ("dimgray", 0x696969),
+  ("dimgrey", 0x696969),
+  ("dodgerblue", 0x1E90FF),
+  ("firebrick", 0xB22222),
+  ("floralwhite", 0xFFFAF0),
+  ("forestgreen", 0x228B22),
+  ("fuchsia", 0xFF00FF),
+  ("gainsboro", 0xDCDCDC),
+  ("ghostwhite", 0xF8F8FF),
+  ("gold", 0xFFD700),
+  ("goldenrod", 0xDAA520),
+  ("gray", 0x808080),
+  ("green", 0x008000),
+  ("greenyellow", 0xADFF2F),
+  ("grey", 0x808080),
+  ("honeydew", 0xF0FFF0),
+  ("hotpink", 0xFF69B4),
+  ("indianred", 0xCD5C5C),
+  ("indigo", 0x4B0082),
+  ("ivory", 0xFFFFF0),
+  ("khaki", 0xF0E68C),
+  ("lavender", 0xE6E6FA),
+  ("lavenderblush", 0xFFF0F5),
+  ("lawngreen", 0x7CFC00),
+  ("lemonchiffon", 0xFFFACD),
+  ("lightblue", 0xADD8E6),
+  ("lightcoral", 0xF08080),
+  ("lightcyan", 0xE0FFFF),
+  ("lightgoldenrodyellow", 0xFAFAD2),
+  ("lightgray", 0xD3D3D3),
+  ("lightgreen", 0x90EE90),
+  ("lightgrey", 0xD3D3D3),
+  ("lightpink", 0xFFB6C1),
+  ("lightsalmon", 0xFFA07A),
+  ("lightseagreen", 0x20B2AA),
+  ("lightskyblue", 0x87CEFA),
+  ("lightslategray", 0x778899),
+  ("lightslategrey", 0x778899),
+  ("lightsteelblue", 0xB0C4DE),
+  ("lightyellow", 0xFFFFE0),
+  ("lime", 0x00FF00),
+  ("limegreen", 0x32CD32),
+  ("linen", 0xFAF0E6),
+  ("magenta", 0xFF00FF),
+  ("maroon", 0x800000),
+  ("mediumaquamarine", 0x66CDAA),
+  ("mediumblue", 0x0000CD),
+  ("mediumorchid", 0xBA55D3),
+  ("mediumpurple", 0x9370DB),
+  ("mediumseagreen", 0x3CB371),
+  ("mediumslateblue", 0x7B68EE),
+  ("mediumspringgreen", 0x00FA9A),
+  ("mediumturquoise", 0x48D1CC),
+  ("mediumvioletred", 0xC71585),
+  ("midnightblue", 0x191970),
+  ("mintcream", 0xF5FFFA),
+  ("mistyrose", 0xFFE4E1),
+  ("moccasin", 0xFFE4B5),
+  ("navajowhite", 0xFFDEAD),
+  ("navy", 0x000080),
+  ("oldlace", 0xFDF5E6),
+  ("olive", 0x808000),
+  ("olivedrab", 0x6B8E23),
+  ("orange", 0xFFA500),
+  ("orangered", 0xFF4500),
+  ("orchid", 0xDA70D6),
+  ("palegoldenrod", 0xEEE8AA),
+  ("palegreen", 0x98FB98),
+  ("paleturquoise", 0xAFEEEE),
+  ("palevioletred", 0xDB7093),
+  ("papayawhip", 0xFFEFD5),
+  ("peachpuff", 0xFFDAB9),
+  ("peru", 0xCD853F),
+  ("pink", 0xFFC0CB),
+  ("plum", 0xDDA0DD),
+  ("powderblue", 0xB0E0E6),
+  ("purple", 0x800080),
+  ("rebeccapurple", 0x663399),
+  ("red", 0xFF0000),
+  ("rosybrown", 0xBC8F8F),
+  ("royalblue", 0x4169E1),
+  ("saddlebrown", 0x8B4513),
+  ("salmon", 0xFA8072),
+  ("sandybrown", 0xF4A460),
+  ("seagreen", 0x2E8B57),
+  ("seashell", 0xFFF5EE),
+  ("sienna", 0xA0522D),
+  ("silver", 0xC0C0C0),
+  ("skyblue", 0x87CEEB),
+  ("slateblue", 0x6A5ACD),
+  ("slategray", 0x708090),
+  ("slategrey", 0x708090),
+  ("snow", 0xFFFAFA),
+  ("springgreen", 0x00FF7F),
+  ("steelblue", 0x4682B4),
+  ("tan", 0xD2B48C),
+  ("teal", 0x008080),
+  ("thistle", 0xD8BFD8),
+  ("tomato", 0xFF6347),
+  ("turquoise", 0x40E0D0),
+  ("violet", 0xEE82EE),
+  ("wheat", 0xF5DEB3),
+  ("white", 0xFFFFFF),
+  ("whitesmoke", 0xF5F5F5),
+  ("yellow", 0xFFFF00),
+  ("yellowgreen", 0x9ACD32),
+];
+
+// parses `#RGB`, `#RGBA`, `#RRGGBB`, `#RRGGBBAA`, `rgb()`, `rgba()`, `hsl()`, `hsla()` and
+// the CSS named colors
+pub fn parse(value: &str) -> Result<Color> {
+  let lower = value.trim().to_ascii_lowercase();
+
+  if let Some(hex) = lower.strip_prefix('#') {
+    return parse_hex(hex).ok_or_else(|| {
+      anyhow!(
+        "invalid color `{}`, expected #RGB, #RGBA, #RRGGBB or #RRGGBBAA",
+        value
+      )
+    });
+  }
+
+  if let Some((function, args)) = lower.strip_suffix(')').and_then(|v| v.split_once('(')) {
+    return parse_function(function.trim(), args)
+      .map_err(|e| anyhow!("invalid color `{}`, {}", value, e));
+  }
+
+  if lower == "transparent" {
+    return Ok(Color::default());
+  }
+
+  NAMED_COLORS
+    .binary_search_by_key(&lower.as_str(), |(name, _)| name)
+    .map(|index| from_rgb(NAMED_COLORS[index].1))
+    .map_err(|_| anyhow!("invalid color `{}`, unknown color name", value))
+}
+
+fn from_rgb(rgb: u32) -> Color {
+  Color {
+    r: (rgb >> 16) as u8,
+    g: (rgb >> 8) as u8,
+    b: rgb as u8,
+    a: 0xFF,
+  }
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+  if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  let digit = |index: usize| u8::from_str_radix(&hex[index..index + 1], 16).ok();
+  let pair = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+
+  let (r, g, b, a) = match hex.len() {
+    3 | 4 => (
+      digit(0)? * 0x11,
+      digit(1)? * 0x11,
+      digit(2)? * 0x11,
+      if hex.len() == 4 { digit(3)? * 0x11 } else { 0xFF },
+    ),
+    6 | 8 => (
+      pair(0)?,
+      pair(2)?,
+      pair(4)?,
+      if hex.len() == 8 { pair(6)? } else { 0xFF },
+    ),
+    _ => return None,
+  };
+
+  Some(Color { r, g, b, a })
+}
+
+fn parse_function(function: &str, args: &str) -> Result<Color> {
+  // accept the legacy comma separated and the modern space separated syntax
+  let args = args.replace([',', '/'], " ");
+  let args: Vec<&str> = args.split_whitespace().collect();
+
+  if args.len() != 3 && args.len() != 4 {
+    bail!("expected 3 or 4 arguments to {}()", function);
+  }
+
+  let a = args.get(3).map(|a| parse_alpha(a)).transpose()?.unwrap_or(0xFF);
+
+  match function {
+    "rgb" | "rgba" => Ok(Color {
+      r: parse_channel(args[0])?,
+      g: parse_channel(args[1])?,
+      b: parse_channel(args[2])?,
+      a,
+    }),
+    "hsl" | "hsla" => {
+      let h = parse_number(args[0].trim_end_matches("deg"))?;
+      let s = parse_number(args[1].trim_end_matches('%'))? / 100.;
+      let l = parse_number(args[2].trim_end_matches('%'))? / 100.;
+      let (r, g, b) = hsl_to_rgb(h, s.clamp(0., 1.), l.clamp(0., 1.));
+      Ok(Color { r, g, b, a })
+    }
+    _ => bail!("unknown color function {}()", function),
+  }
+}
+
+fn parse_number(value: &str) -> Result<f32> {
+  value
+    .parse::<f32>()
+    .map_err(|_| anyhow!("`{}` is not a number", value))
+}
+
+fn to_u8(value: f32) -> u8 {
+  value.round().clamp(0., 255.) as u8
+}
+
+// 0-255 or a percentage
+fn parse_channel(value: &str) -> Result<u8> {
+  match value.strip_suffix('%') {
+    Some(percent) => Ok(to_u8(parse_number(percent)? * 255. / 100.)),
+    None => Ok(to_u8(parse_number(value)?)),
+  }
+}
+
+// 0-1 or a percentage
+fn parse_alpha(value: &str) -> Result<u8> {
+  match value.strip_suffix('%') {
+    Some(percent) => Ok(to_u8(parse_number(percent)? * 255. / 100.)),
+    None => Ok(to_u8(parse_number(value)? * 255.)),
+  }
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+  let h = h.rem_euclid(360.) / 60.;
+  let c = (1. - (2. * l - 1.).abs()) * s;
+  let x = c * (1. - (h % 2. - 1.).abs());
+  let m = l - c / 2.;
+
+  let (r, g, b) = match h as u32 {
+    0 => (c, x, 0.),
+    1 => (x, c, 0.),
+    2 => (0., c, x),
+    3 => (0., x, c),
+    4 => (x, 0., c),
+    _ => (c, 0., x),
+  };
+
+  (
+    to_u8((r + m) * 255.),
+    to_u8((g + m) * 255.),
+    to_u8((b + m) * 255.),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rgba(value: &str) -> (u8, u8, u8, u8) {
+    let color = parse(value).unwrap();
+    (color.r, color.g, color.b, color.a)
+  }
+
+  #[test]
+  fn parses_hex() {
+    assert_eq!(rgba("#1a2B3c"), (0x1A, 0x2B, 0x3C, 0xFF));
+    assert_eq!(rgba("#1a2b3c80"), (0x1A, 0x2B, 0x3C, 0x80));
+    // the shorthand repeats each digit
+    assert_eq!(rgba("#f80"), (0xFF, 0x88, 0x00, 0xFF));
+    assert_eq!(rgba("#f808"), (0xFF, 0x88, 0x00, 0x88));
+  }
+
+  #[test]
+  fn parses_rgb() {
+    assert_eq!(rgba("rgb(255, 128, 0)"), (255, 128, 0, 255));
+    assert_eq!(rgba("rgb(100% 50% 0%)"), (255, 128, 0, 255));
+    assert_eq!(rgba("rgba(255, 128, 0, 0.5)"), (255, 128, 0, 128));
+    assert_eq!(rgba("rgb(255 128 0 / 25%)"), (255, 128, 0, 64));
+    // out of range channels are clamped
+    assert_eq!(rgba("rgb(300, -5, 0)"), (255, 0, 0, 255));
+  }
+
+  #[test]
+  fn parses_hsl() {
+    assert_eq!(rgba("hsl(0, 100%, 50%)"), (255, 0, 0, 255));
+    assert_eq!(rgba("hsl(120deg 100% 25%)"), (0, 128, 0, 255));
+    assert_eq!(rgba("hsl(240, 100%, 50%)"), (0, 0, 255, 255));
+    assert_eq!(rgba("hsl(-120, 100%, 50%)"), (0, 0, 255, 255));
+    assert_eq!(rgba("hsla(0, 0%, 100%, 0.5)"), (255, 255, 255, 128));
+  }
+
+  #[test]
+  fn parses_names() {
+    assert_eq!(rgba("RebeccaPurple"), (0x66, 0x33, 0x99, 0xFF));
+    assert_eq!(rgba(" white "), (255, 255, 255, 255));
+    assert_eq!(rgba("transparent"), (0, 0, 0, 0));
+  }
+
+  #[test]
+  fn rejects_invalid_colors() {
+    for value in [
+      "#GGG",
+      "#12",
+      "#12345",
+      "rgb(1, 2)",
+      "rgb(1, 2, 3, 4, 5)",
+      "rgb(a, b, c)",
+      "cmyk(1, 2, 3)",
+      "notacolor",
+      "",
+    ] {
+      assert!(parse(value).is_err(), "{:?} was accepted", value);
+    }
+  }
+}
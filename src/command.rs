@@ -9,67 +9,151 @@ use crate::config;
 use log::{debug, error, info, trace};
 use std::os::unix::fs::PermissionsExt;
 
-#[derive(Clone)]
+/// An entry of the menu
+#[derive(Debug, Clone)]
 pub struct Command {
   pub path: PathBuf,
   pub name: String,
+  /// A secondary text shown next to the name, the full path for binaries
+  pub description: Option<String>,
+  /// Byte offsets of the characters of the name matched by the filter
+  pub matches: Vec<usize>,
+  /// And of the description
+  pub description_matches: Vec<usize>,
   weight: u32,
 }
 
+/// The entries of the menu and the ones matching the input
 pub struct CommandList {
   initial: Vec<PathBuf>,
-  pub filtered: Vec<Command>,
+  ignore_case: bool,
+  descriptions: bool,
+  search_descriptions: bool,
+  pub(crate) filtered: Vec<Command>,
 }
 
 impl CommandList {
+  /// The binaries found in PATH
   pub fn new(app_config: &config::AppConfig) -> std::io::Result<Self> {
     let initial = gather_commands(&app_config.static_config)?;
-    let filtered = Self::filter_data(None::<&String>, &initial, &app_config.history);
+    Ok(Self::from_paths(app_config, initial))
+  }
+  /// A list of the given entries instead of the binaries in PATH
+  pub fn from_paths(app_config: &config::AppConfig, initial: Vec<PathBuf>) -> Self {
+    let ignore_case = app_config.static_config.ignore_case.unwrap_or(true);
+    // the descriptions are only made and searched when they are shown
+    let descriptions = app_config.static_config.descriptions();
+    let search_descriptions = descriptions
+      && app_config
+        .static_config
+        .search_descriptions
+        .unwrap_or(false);
+    let filtered = Self::filter_data(
+      None,
+      &initial,
+      &app_config.history,
+      ignore_case,
+      descriptions,
+      search_descriptions,
+    );
 
-    Ok(Self { initial, filtered })
+    Self {
+      initial,
+      ignore_case,
+      descriptions,
+      search_descriptions,
+      filtered,
+    }
   }
-  pub fn filter(&mut self, filter: &String, history: &config::History) {
-    self.filtered = Self::filter_data(Some(filter), &self.initial, history)
+  /// Keeps the entries matching the filter, ordered by the usage statistic
+  pub fn filter(&mut self, filter: &str, history: &config::History) {
+    self.filtered = Self::filter_data(
+      Some(filter),
+      &self.initial,
+      history,
+      self.ignore_case,
+      self.descriptions,
+      self.search_descriptions,
+    )
+  }
+  /// The entries matching the last filter
+  pub fn filtered(&self) -> &[Command] {
+    &self.filtered
   }
   pub fn filtered_len(&self) -> usize {
     self.filtered.len()
   }
+  pub fn total_len(&self) -> usize {
+    self.initial.len()
+  }
   fn filename(path: &Path) -> Option<String> {
     path
       .file_name()
       .and_then(|s| s.to_str())
-      .map(|s| String::from(s))
+      .map(String::from)
   }
   fn filter_data(
-    filter: Option<&String>,
-    data: &Vec<PathBuf>,
+    filter: Option<&str>,
+    data: &[PathBuf],
     history: &config::History,
+    ignore_case: bool,
+    descriptions: bool,
+    search_descriptions: bool,
   ) -> Vec<Command> {
+    let filter = filter.map(|filter| {
+      if ignore_case {
+        filter.to_lowercase()
+      } else {
+        String::from(filter)
+      }
+    });
+
     let mut list = data
       .iter()
       .filter_map(|path| {
         Self::filename(path).and_then(|name| {
-          if let Some(filter) = filter {
-            Some(name)
-              .filter(|name| name.to_lowercase().starts_with(filter))
-              .map(|name| Command {
-                path: path.clone(),
-                weight: history.get_weight(&name),
-                name,
-              })
-          } else {
-            Some(Command {
-              path: path.clone(),
-              weight: history.get_weight(&name),
-              name,
-            })
-          }
+          let description = path.to_str().filter(|_| descriptions).map(String::from);
+          let (matches, description_matches) = match filter.as_ref() {
+            Some(filter) => {
+              let matches = match_prefix(&name, filter, ignore_case);
+              // the directory of the path is searched anywhere, but only if the name does not
+              // match, the name at its end is matched already
+              let directory = path.parent().and_then(Path::to_str).map_or(0, str::len);
+              let description_matches = description
+                .as_deref()
+                .filter(|_| search_descriptions && matches.is_none())
+                .and_then(|description| {
+                  match_substring(&description[..directory], filter, ignore_case)
+                });
+              if matches.is_none() && description_matches.is_none() {
+                return None;
+              }
+              (
+                matches.unwrap_or_default(),
+                description_matches.unwrap_or_default(),
+              )
+            }
+            None => (Vec::new(), Vec::new()),
+          };
+          Some(Command {
+            path: path.clone(),
+            weight: history.get_weight(&name),
+            description,
+            matches,
+            description_matches,
+            name,
+          })
         })
       })
       .collect::<Vec<Command>>();
 
-    // sort the weights
-    list.sort_by(|a, b| b.weight.cmp(&a.weight));
+    // sort the weights, the entries matched by their name first
+    list.sort_by_key(|c| {
+      (
+        c.matches.is_empty() && !c.description_matches.is_empty(),
+        std::cmp::Reverse(c.weight),
+      )
+    });
 
     list
   }
@@ -80,8 +164,8 @@ impl Display for CommandList {
     write!(
       f,
       "List has {} filtered entries and {} in total",
-      self.filtered.len(),
-      self.initial.len()
+      self.filtered_len(),
+      self.total_len()
     )
   }
 }
@@ -93,6 +177,10 @@ impl Display for Command {
 }
 
 impl Command {
+  /// The name to look up in the icon theme
+  pub fn icon_name(&self) -> &str {
+    &self.name
+  }
   pub fn binary(&self) -> String {
     String::from(
       self
@@ -104,6 +192,35 @@ impl Command {
   }
 }
 
+// the positions of the characters of `name` that start with `filter`, None if it does not
+fn match_prefix(name: &str, filter: &str, ignore_case: bool) -> Option<Vec<usize>> {
+  let mut rest = filter;
+  let mut matches = Vec::new();
+
+  for (index, c) in name.char_indices() {
+    if rest.is_empty() {
+      break;
+    }
+    let c = if ignore_case {
+      c.to_lowercase().collect()
+    } else {
+      String::from(c)
+    };
+    rest = rest.strip_prefix(c.as_str())?;
+    matches.push(index);
+  }
+
+  rest.is_empty().then_some(matches)
+}
+
+// the positions of the first occurrence of `filter` within `text`
+fn match_substring(text: &str, filter: &str, ignore_case: bool) -> Option<Vec<usize>> {
+  text.char_indices().find_map(|(start, _)| {
+    match_prefix(&text[start..], filter, ignore_case)
+      .map(|matches| matches.into_iter().map(|offset| start + offset).collect())
+  })
+}
+
 fn gather_commands(config: &config::StaticConfig) -> std::io::Result<Vec<PathBuf>> {
   let mut list = Vec::<PathBuf>::new();
 
@@ -165,7 +282,7 @@ fn gather_commands(config: &config::StaticConfig) -> std::io::Result<Vec<PathBuf
   Ok(list)
 }
 
-pub fn launch(command: &Command) -> i32 {
+pub(crate) fn launch(command: &Command) -> i32 {
   match std::process::Command::new(&command.path)
     .stdout(Stdio::null())
     .stdin(Stdio::null())
@@ -1,11 +1,19 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, bail, Context, Result};
 use core::fmt;
-use log::{debug, info};
-use serde::{de::Visitor, Deserialize, Deserializer};
-use std::io::{BufRead, BufReader, Write};
-use std::path::{Path, PathBuf};
+use log::{debug, info, warn};
+use serde::{
+  de::{DeserializeOwned, Visitor},
+  Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_yaml::Value;
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{color, theme};
+
+pub use crate::check::{Diagnostic, Report};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Color {
@@ -15,12 +23,167 @@ pub struct Color {
   pub a: u8,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+  #[default]
+  Top,
+  Bottom,
+  // a floating box in the middle of the screen
+  Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Width {
+  Pixels(u32),
+  Percent(f32),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Margin {
+  All(i32),
+  Sides {
+    top: Option<i32>,
+    right: Option<i32>,
+    bottom: Option<i32>,
+    left: Option<i32>,
+  },
+}
+
+/// How the surface treats the exclusive zones of other surfaces like status bars
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExclusiveZone {
+  // stretch over other surfaces
+  #[default]
+  Ignore,
+  // stay clear of other surfaces
+  Respect,
+  // stay clear of other surfaces and reserve the space of the menu
+  Reserve,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+  Background,
+  Bottom,
+  #[default]
+  Top,
+  Overlay,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardInteractivity {
+  Exclusive,
+  #[default]
+  OnDemand,
+}
+
+/// The parts of the input line, the list takes the space left between them
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Element {
+  Prompt,
+  Input,
+  // the number of matching and of all entries
+  Counter,
+  List,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientKind {
+  #[default]
+  Linear,
+  // around the center of the surface
+  Radial,
+}
+
+/// A color of the gradient, spread evenly with the others unless the position (0 to 1) is given
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum GradientStop {
+  Color(Color),
+  At { color: Color, position: f32 },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Gradient {
+  #[serde(rename = "type")]
+  pub kind: Option<GradientKind>,
+  // the direction of a linear gradient in degrees like in CSS, 0 points up and 90 to the right
+  pub angle: Option<f32>,
+  pub stops: Vec<GradientStop>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMode {
+  // cover the whole surface keeping the aspect ratio
+  #[default]
+  Scale,
+  Tile,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackgroundImage {
+  pub path: PathBuf,
+  pub mode: Option<ImageMode>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct Style {
   pub highlight_color: Option<Color>,
   pub foreground_color: Option<Color>,
   pub background_color: Option<Color>,
-  pub height: u32,
+  // drawn instead of the background color
+  pub background_gradient: Option<Gradient>,
+  // drawn on top of the background color or gradient
+  pub background_image: Option<BackgroundImage>,
+  pub selected_background_color: Option<Color>,
+  // the box around the selected entry, the padding is the space between the entry and its edge
+  pub selected_padding: Option<f32>,
+  pub selected_corner_radius: Option<f32>,
+  pub selected_border_color: Option<Color>,
+  pub selected_border_width: Option<f32>,
+  // the space above and below the box of an entry
+  pub row_padding: Option<f32>,
+  // lines between the rows of a vertical list and the entries of the bar
+  pub separator_color: Option<Color>,
+  pub separator_width: Option<f32>,
+  // the characters of an entry matched by the input
+  pub match_color: Option<Color>,
+  pub match_underline: Option<bool>,
+  pub placeholder_color: Option<Color>,
+  // show the descriptions of the entries
+  pub descriptions: Option<bool>,
+  pub description_color: Option<Color>,
+  // the order of the elements, the ones after the list are aligned to the right
+  pub elements: Option<Vec<Element>>,
+  // the space reserved for the input, pixels or a percentage of the width
+  pub input_width: Option<Width>,
+  pub height: Option<u32>,
+  pub position: Option<Position>,
+  pub width: Option<Width>,
+  pub margin: Option<Margin>,
+  pub exclusive_zone: Option<ExclusiveZone>,
+  pub lines: Option<u32>,
+  pub border_color: Option<Color>,
+  pub border_width: Option<f32>,
+  pub corner_radius: Option<f32>,
+  pub icons: Option<bool>,
+  pub icon_theme: Option<String>,
+}
+
+/// How the surface behaves, kept apart from the style so themes cannot change it
+#[derive(Debug, Default, Deserialize)]
+pub struct Surface {
+  pub layer: Option<Layer>,
+  pub keyboard_interactivity: Option<KeyboardInteractivity>,
+  pub close_on_unfocus: Option<bool>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -29,82 +192,302 @@ pub struct Font {
   pub name: Option<String>,
   pub spacing: Option<f32>,
   pub size: Option<f32>,
+  // fonts (paths or names) to try for characters the primary font does not cover
+  pub fallback: Option<Vec<String>>,
 }
 
 #[derive(Debug, Default, Deserialize)]
 pub struct StaticConfig {
   pub blacklist: Option<Vec<String>>,
   pub whitelist: Option<Vec<String>>,
+  pub prompt: Option<String>,
+  // shown in place of the empty input
+  pub placeholder: Option<String>,
+  pub ignore_case: Option<bool>,
+  // match the input against the descriptions of the entries as well
+  pub search_descriptions: Option<bool>,
+  // a hidden input without entries, the input is printed to stdout
+  pub password: Option<bool>,
+  pub theme: Option<String>,
   pub style: Option<Style>,
+  pub surface: Option<Surface>,
   pub font: Option<Font>,
 }
 
+/// A theme only gets deserialized to validate it, the values are merged into the StaticConfig
+#[derive(Debug, Default, Deserialize)]
+pub struct Theme {
+  pub style: Option<Style>,
+  pub font: Option<Font>,
+}
+
+/// The height of a row
+pub static DEFAULT_HEIGHT: u32 = 32;
+
+// the centered box always lists the entries vertically
+static DEFAULT_CENTER_LINES: u32 = 10;
+
+impl StaticConfig {
+  pub fn position(&self) -> Position {
+    self
+      .style
+      .as_ref()
+      .and_then(|s| s.position)
+      .unwrap_or_default()
+  }
+
+  /// The number of lines of the vertical list, 0 for the horizontal one
+  pub fn lines(&self) -> u32 {
+    if self.password() {
+      return 0;
+    }
+    match (self.style.as_ref().and_then(|s| s.lines), self.position()) {
+      (Some(lines), Position::Center) => lines.max(1),
+      (Some(lines), _) => lines,
+      (None, Position::Center) => DEFAULT_CENTER_LINES,
+      (None, _) => 0,
+    }
+  }
+
+  pub fn descriptions(&self) -> bool {
+    !self.password()
+      && self
+        .style
+        .as_ref()
+        .and_then(|s| s.descriptions)
+        .unwrap_or(false)
+  }
+
+  pub fn password(&self) -> bool {
+    self.password.unwrap_or(false)
+  }
+
+  /// The number of rows of the surface, the bar gets a second one for the description
+  pub fn rows(&self) -> u32 {
+    match self.lines() {
+      0 if self.descriptions() => 2,
+      lines => lines + 1,
+    }
+  }
+
+  /// One row for the input and one for each line of a vertical list or the description plus the border
+  pub fn height(&self) -> u32 {
+    let row = self
+      .style
+      .as_ref()
+      .and_then(|s| s.height)
+      .unwrap_or(DEFAULT_HEIGHT);
+    row * self.rows() + 2 * self.border_width().ceil() as u32
+  }
+
+  pub fn border_width(&self) -> f32 {
+    self
+      .style
+      .as_ref()
+      .and_then(|s| s.border_width)
+      .unwrap_or(0.)
+      .max(0.)
+  }
+}
+
+/// How often each entry was launched, frequent ones are listed first
 #[derive(Debug, Default)]
 pub struct History(HashMap<String, u32>);
 
+/// The config of a menu along with the usage statistic of its entries
 #[derive(Debug)]
 pub struct AppConfig {
   pub static_config: StaticConfig,
   pub history: History,
-  args: Args,
+  // where the usage statistic is stored, nowhere if unset
+  history_path: Option<PathBuf>,
 }
 
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-pub struct Args {
-  #[clap(short, long)]
+/// Where the config and the usage statistic are read from and what overrides the config files
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+  /// The user config file instead of `$XDG_CONFIG_HOME/rmenu/config.yaml`
   pub config: Option<PathBuf>,
-  #[clap(long)]
+  /// The usage statistic instead of `$XDG_STATE_HOME/rmenu/history`
   pub history: Option<PathBuf>,
+  /// A theme taking precedence over the one set in the config files
+  pub theme: Option<String>,
+  /// Merged over the config files one after the other, see [`override_value`]
+  pub overrides: Vec<Value>,
+}
+
+// the system wide rmenu config directories from `$XDG_CONFIG_DIRS` ordered by precedence
+fn system_config_dirs() -> Vec<PathBuf> {
+  let dirs = std::env::var("XDG_CONFIG_DIRS")
+    .ok()
+    .filter(|dirs| !dirs.is_empty())
+    .unwrap_or_else(|| String::from("/etc/xdg"));
+
+  dirs
+    .split(':')
+    .filter(|dir| !dir.is_empty())
+    .map(|dir| Path::new(dir).join("rmenu"))
+    .collect()
 }
 
-pub fn parse() -> Result<AppConfig> {
-  let mut args = Args::parse();
+// all rmenu config directories ordered by precedence, the user one (`$XDG_CONFIG_HOME`) first
+pub(crate) fn config_dirs() -> Vec<PathBuf> {
+  dirs::config_dir()
+    .map(|dir| dir.join("rmenu"))
+    .into_iter()
+    .chain(system_config_dirs())
+    .collect()
+}
 
-  if args.config.is_none() {
-    args.config = dirs::home_dir().map(|h| h.join(".config/rmenu/config.yaml"))
+impl Options {
+  fn config_path(&self) -> Option<PathBuf> {
+    self
+      .config
+      .clone()
+      .or_else(|| dirs::config_dir().map(|d| d.join("rmenu/config.yaml")))
   }
 
-  let static_config = if let Some(file) = &args.config.as_ref().and_then(|path| {
-    std::fs::File::open(&path).ok().map(|file| {
-      info!("Reading config from {}", path.display());
-      file
-    })
-  }) {
-    serde_yaml::from_reader(file)?
-  } else {
-    Default::default()
-  };
+  /// All existing config files, the system wide ones first and the user one last
+  pub fn config_layers(&self) -> Vec<PathBuf> {
+    system_config_dirs()
+      .into_iter()
+      .rev()
+      .map(|dir| dir.join("config.yaml"))
+      .chain(self.config_path())
+      .filter(|path| path.exists())
+      .collect()
+  }
+
+  fn history_path(&self) -> Option<PathBuf> {
+    self
+      .history
+      .clone()
+      .or_else(|| dirs::state_dir().map(|d| d.join("rmenu/history")))
+  }
+}
+
+/// Wraps `value` into mappings for each part of the dotted `key`, e.g. `style.height`, to be
+/// merged over the config files, unknown keys are rejected
+pub fn override_value(key: &str, mut value: Value) -> Result<Value> {
+  for part in key.rsplit('.') {
+    if part.is_empty() {
+      bail!("invalid key `{}`", key);
+    }
+    let mut mapping = serde_yaml::Mapping::new();
+    mapping.insert(Value::String(String::from(part)), value);
+    value = Value::Mapping(mapping);
+  }
+
+  let mut unknown = false;
+  serde_ignored::deserialize::<_, _, StaticConfig>(value.clone(), |_| unknown = true)?;
+  if unknown {
+    bail!("unknown key `{}`", key);
+  }
+
+  Ok(value)
+}
+
+// deep merges `overlay` into `base`, mappings are merged key by key and everything else is replaced
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+  match (base, overlay) {
+    (Value::Mapping(base), Value::Mapping(overlay)) => {
+      for (key, value) in overlay {
+        match base.get_mut(&key) {
+          Some(existing) => merge(existing, value),
+          None => {
+            base.insert(key, value);
+          }
+        }
+      }
+    }
+    (_, Value::Null) => {}
+    (base, overlay) => *base = overlay,
+  }
+}
+
+// reads a single config layer, it gets validated as `T` first to report errors with their location
+pub(crate) fn load_layer<T: DeserializeOwned>(path: &Path) -> Result<Value> {
+  info!("Reading config from {}", path.display());
+  let source = std::fs::read_to_string(path)
+    .with_context(|| format!("Failed to read config {}", path.display()))?;
+  serde_yaml::from_str::<T>(&source)
+    .with_context(|| format!("Invalid config {}", path.display()))?;
+  Ok(serde_yaml::from_str(&source)?)
+}
 
-  if args.history.is_none() {
-    args.history = dirs::home_dir().map(|h| h.join(".config/rmenu/history"))
+/// Reads the config files and the theme they use, merges the overrides over them and loads the
+/// usage statistic
+pub fn load(options: Options) -> Result<AppConfig> {
+  let mut value = Value::Null;
+  for path in options.config_layers() {
+    merge(&mut value, load_layer::<StaticConfig>(&path)?);
   }
+  for value_override in options.overrides.iter().cloned() {
+    merge(&mut value, value_override);
+  }
+
+  let theme = options.theme.clone().or_else(|| {
+    value
+      .get("theme")
+      .and_then(|theme| theme.as_str())
+      .map(String::from)
+  });
+
+  if let Some(name) = theme {
+    let mut base = theme::load(&name)?;
+    merge(&mut base, value);
+    value = base;
+  }
+
+  let static_config = serde_yaml::from_value(value)?;
 
-  let history = if let Some(path) = &args.history {
-    History::from_path(path.as_path())
-  } else {
-    Default::default()
+  let history_path = options.history_path();
+
+  // fall back to the location used before the history moved to `$XDG_STATE_HOME`, unless the
+  // history was given explicitly
+  let legacy_history = dirs::home_dir()
+    .map(|h| h.join(".config/rmenu/history"))
+    .filter(|_| options.history.is_none());
+
+  let history = match &history_path {
+    Some(path) if path.exists() => History::from_path(path),
+    Some(_) => legacy_history
+      .filter(|path| path.exists())
+      .map(|path| History::from_path(&path))
+      .unwrap_or_default(),
+    None => Default::default(),
   };
 
   Ok(AppConfig {
-    args,
+    history_path,
     history,
     static_config,
   })
 }
 
+/// A config without a usage statistic, nothing gets stored
+impl From<StaticConfig> for AppConfig {
+  fn from(static_config: StaticConfig) -> Self {
+    AppConfig {
+      static_config,
+      history: History::default(),
+      history_path: None,
+    }
+  }
+}
+
 impl AppConfig {
   pub fn increment_and_store_history(&mut self, binary: String) -> std::io::Result<()> {
     (*self.history.0.entry(binary).or_insert(0)) += 1;
     self.store_history()
   }
   fn store_history(&self) -> std::io::Result<()> {
-    if let Some(path) = self.args.history.as_ref().and_then(|path| {
+    if let Some(path) = self.history_path.as_ref().and_then(|path| {
       path
         .parent()
         .and_then(|parent| std::fs::create_dir_all(parent).ok().map(|_| path))
     }) {
-      self.history.to_path(&path)
+      self.history.to_path(path)
     } else {
       Ok(())
     }
@@ -117,7 +500,19 @@ impl History {
       let re = regex::Regex::new(r"^(?P<binary>.*)\|(?P<weight>\d+)$").unwrap();
       let x = BufReader::new(file)
         .lines()
-        .filter_map(Result::ok)
+        .map_while(|line| match line {
+          Ok(line) => Some(Some(line)),
+          // a line that is no valid UTF-8 is skipped, any other error would repeat on every read
+          Err(e) if e.kind() == ErrorKind::InvalidData => {
+            warn!("Skip unreadable history line - {}", e);
+            Some(None)
+          }
+          Err(e) => {
+            warn!("Failed to read history - {}", e);
+            None
+          }
+        })
+        .flatten()
         .filter_map(|line| {
           debug!("Line {}", line);
           re.captures(&line).and_then(|groups| {
@@ -145,77 +540,131 @@ impl History {
   pub fn to_path(&self, path: &Path) -> std::io::Result<()> {
     let mut file = std::fs::File::create(path)?;
     for (binary, weight) in &self.0 {
-      file.write(format!("{}|{}\n", binary, weight).as_bytes())?;
+      file.write_all(format!("{}|{}\n", binary, weight).as_bytes())?;
     }
 
     Ok(())
   }
 }
 
-impl<'de> Deserialize<'de> for Color {
-  fn deserialize<D>(deserializer: D) -> Result<Color, D::Error>
+impl FromStr for Color {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    color::parse(value)
+  }
+}
+
+impl Margin {
+  // top, right, bottom and left margin
+  pub fn sides(&self) -> (i32, i32, i32, i32) {
+    match *self {
+      Margin::All(margin) => (margin, margin, margin, margin),
+      Margin::Sides {
+        top,
+        right,
+        bottom,
+        left,
+      } => (
+        top.unwrap_or(0),
+        right.unwrap_or(0),
+        bottom.unwrap_or(0),
+        left.unwrap_or(0),
+      ),
+    }
+  }
+}
+
+impl FromStr for Width {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    let value = value.trim();
+    if let Some(percent) = value.strip_suffix('%') {
+      match percent.trim().parse::<f32>() {
+        Ok(percent) if percent > 0. && percent <= 100. => Ok(Width::Percent(percent)),
+        _ => bail!("invalid width `{}`, expected a percentage between 0% and 100%", value),
+      }
+    } else {
+      value
+        .trim_end_matches("px")
+        .trim()
+        .parse::<u32>()
+        .map(Width::Pixels)
+        .map_err(|_| anyhow!("invalid width `{}`, expected pixels or a percentage", value))
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for Width {
+  fn deserialize<D>(deserializer: D) -> Result<Width, D::Error>
   where
     D: Deserializer<'de>,
   {
-    struct ColorVisitor;
+    struct WidthVisitor;
 
-    fn parse<E>(value: &str) -> Result<Color, E>
-    where
-      E: serde::de::Error,
-    {
-      let mut color: Color = Default::default();
-      let re = regex::Regex::new(
-        r"^#(?P<r>[0-9a-f]{2})(?P<g>[0-9a-f]{2})(?P<b>[0-9a-f]{2})(?P<a>[0-9a-f]{2})$",
-      )
-      .unwrap();
-      if let Some(groups) = re.captures(&value.to_ascii_lowercase()) {
-        if let Some(r) = groups.name("r") {
-          if let Ok(r) = u8::from_str_radix(r.as_str(), 16) {
-            color.r = r;
-          }
-        }
-        if let Some(g) = groups.name("g") {
-          if let Ok(g) = u8::from_str_radix(g.as_str(), 16) {
-            color.g = g;
-          }
-        }
-        if let Some(b) = groups.name("b") {
-          if let Ok(b) = u8::from_str_radix(b.as_str(), 16) {
-            color.b = b;
-          }
-        }
-        if let Some(a) = groups.name("a") {
-          if let Ok(a) = u8::from_str_radix(a.as_str(), 16) {
-            color.a = a;
-          }
-        }
+    impl<'de> Visitor<'de> for WidthVisitor {
+      type Value = Width;
+
+      fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a width in pixels or a percentage")
       }
 
-      Ok(color)
+      fn visit_u64<E>(self, value: u64) -> Result<Width, E>
+      where
+        E: serde::de::Error,
+      {
+        u32::try_from(value)
+          .map(Width::Pixels)
+          .map_err(|_| E::custom(format!("width {} is too large", value)))
+      }
+
+      fn visit_str<E>(self, value: &str) -> Result<Width, E>
+      where
+        E: serde::de::Error,
+      {
+        value.parse().map_err(E::custom)
+      }
     }
 
+    deserializer.deserialize_any(WidthVisitor)
+  }
+}
+
+impl Serialize for Color {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&format!(
+      "#{:02X}{:02X}{:02X}{:02X}",
+      self.r, self.g, self.b, self.a
+    ))
+  }
+}
+
+impl<'de> Deserialize<'de> for Color {
+  fn deserialize<D>(deserializer: D) -> Result<Color, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    struct ColorVisitor;
+
     impl<'de> Visitor<'de> for ColorVisitor {
       type Value = Color;
 
       fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("struct Color")
+        formatter.write_str("a color string")
       }
 
       fn visit_str<E>(self, value: &str) -> Result<Color, E>
       where
         E: serde::de::Error,
       {
-        parse(value)
-      }
-
-      fn visit_string<E>(self, value: String) -> Result<Color, E>
-      where
-        E: serde::de::Error,
-      {
-        parse(&value)
+        value.parse().map_err(E::custom)
       }
     }
 
-    deserializer.deserialize_string(ColorVisitor)
+    deserializer.deserialize_str(ColorVisitor)
   }
 }
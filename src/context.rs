@@ -0,0 +1,73 @@
+use log::info;
+
+use crate::{
+  command::{Command, CommandList},
+  config::AppConfig,
+};
+
+/// The state of the menu shared by all frontends
+pub struct AppContext {
+  pub(crate) config: AppConfig,
+  pub(crate) input: String,
+  pub(crate) list: CommandList,
+  pub(crate) current_index: usize,
+}
+
+impl AppContext {
+  /// The binaries found in PATH, none for a password
+  pub fn new(config: AppConfig) -> std::io::Result<Self> {
+    let list = if config.static_config.password() {
+      CommandList::from_paths(&config, Vec::new())
+    } else {
+      CommandList::new(&config)?
+    };
+    Ok(Self::with_list(config, list))
+  }
+  /// The given entries instead of the binaries in PATH
+  pub fn with_list(config: AppConfig, list: CommandList) -> Self {
+    Self {
+      config,
+      input: String::new(),
+      list,
+      current_index: 0,
+    }
+  }
+  pub fn config(&self) -> &AppConfig {
+    &self.config
+  }
+  /// Gives the config back once the menu is closed
+  pub fn into_config(self) -> AppConfig {
+    self.config
+  }
+  /// The text typed so far
+  pub fn input(&self) -> &str {
+    &self.input
+  }
+  pub fn list(&self) -> &CommandList {
+    &self.list
+  }
+  /// The position of the selection within the filtered entries
+  pub fn current_index(&self) -> usize {
+    self.current_index
+  }
+  /// Selects the entry at the index, the last one if the index is past the end
+  pub fn select(&mut self, index: usize) {
+    self.current_index = index.min(self.list.filtered_len().saturating_sub(1));
+  }
+  /// The selected entry, None if no entry matches the input
+  pub fn target(&self) -> Option<&Command> {
+    self.list.filtered.get(self.current_index)
+  }
+  fn filter(&mut self) {
+    self.list.filter(&self.input, &self.config.history);
+    info!("{}", self.list);
+  }
+  pub(crate) fn pop_and_filter(&mut self) {
+    self.input.pop();
+    self.filter()
+  }
+  pub(crate) fn append_and_filter(&mut self, input: &str) {
+    self.input.push_str(input);
+    self.filter()
+  }
+}
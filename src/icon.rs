@@ -0,0 +1,374 @@
+use std::{
+  collections::HashMap,
+  fs::File,
+  path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use resvg::{
+  tiny_skia::{Pixmap, Transform},
+  usvg::{self, TreeParsing},
+};
+
+static DEFAULT_THEME: &str = "hicolor";
+static EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+// a decoded icon or image in the premultiplied ARGB format raqote draws from
+pub struct Icon {
+  pub width: i32,
+  pub height: i32,
+  pub data: Vec<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DirectoryType {
+  Fixed,
+  Scalable,
+  Threshold,
+}
+
+// a subdirectory of a theme as described by its index.theme
+struct Directory {
+  name: String,
+  size: u32,
+  scale: u32,
+  kind: DirectoryType,
+  min_size: u32,
+  max_size: u32,
+  threshold: u32,
+}
+
+struct Theme {
+  // the same theme can be spread over multiple base directories
+  paths: Vec<PathBuf>,
+  directories: Vec<Directory>,
+  inherits: Vec<String>,
+}
+
+// resolves icon names following the freedesktop icon theme specification
+pub struct IconLoader {
+  base_dirs: Vec<PathBuf>,
+  theme: String,
+  themes: HashMap<String, Option<Theme>>,
+  // decoded icons by name and size, None if there is no such icon
+  cache: HashMap<(String, u32), Option<Icon>>,
+}
+
+impl Directory {
+  fn matches_size(&self, size: u32, scale: u32) -> bool {
+    if self.scale != scale {
+      return false;
+    }
+    match self.kind {
+      DirectoryType::Fixed => self.size == size,
+      DirectoryType::Scalable => self.min_size <= size && size <= self.max_size,
+      DirectoryType::Threshold => {
+        self.size.saturating_sub(self.threshold) <= size && size <= self.size + self.threshold
+      }
+    }
+  }
+
+  fn size_distance(&self, size: u32, scale: u32) -> u32 {
+    let (min, max) = match self.kind {
+      DirectoryType::Fixed => (self.size, self.size),
+      DirectoryType::Scalable => (self.min_size, self.max_size),
+      DirectoryType::Threshold => (
+        self.size.saturating_sub(self.threshold),
+        self.size + self.threshold,
+      ),
+    };
+    let (min, max, size) = (min * self.scale, max * self.scale, size * scale);
+    min.saturating_sub(size).max(size.saturating_sub(max))
+  }
+}
+
+impl Theme {
+  fn load(name: &str, base_dirs: &[PathBuf]) -> Option<Self> {
+    let paths: Vec<PathBuf> = base_dirs
+      .iter()
+      .map(|dir| dir.join(name))
+      .filter(|path| path.is_dir())
+      .collect();
+
+    let index = paths
+      .iter()
+      .find_map(|path| std::fs::read_to_string(path.join("index.theme")).ok())?;
+    let sections = parse_ini(&index);
+
+    let header = sections.get("Icon Theme")?;
+    let list = |key: &str| -> Vec<String> {
+      header
+        .get(key)
+        .map(|value| {
+          value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect()
+        })
+        .unwrap_or_default()
+    };
+
+    let directories = list("Directories")
+      .into_iter()
+      .chain(list("ScaledDirectories"))
+      .filter_map(|name| {
+        let section = sections.get(&name)?;
+        let number = |key: &str| section.get(key).and_then(|v| v.parse::<u32>().ok());
+        let size = number("Size")?;
+        let kind = match section.get("Type").map(String::as_str) {
+          Some("Fixed") => DirectoryType::Fixed,
+          Some("Scalable") => DirectoryType::Scalable,
+          _ => DirectoryType::Threshold,
+        };
+        Some(Directory {
+          size,
+          scale: number("Scale").unwrap_or(1),
+          kind,
+          min_size: number("MinSize").unwrap_or(size),
+          max_size: number("MaxSize").unwrap_or(size),
+          threshold: number("Threshold").unwrap_or(2),
+          name,
+        })
+      })
+      .collect();
+
+    Some(Self {
+      paths,
+      directories,
+      inherits: list("Inherits"),
+    })
+  }
+
+  // the files the icon could be stored in within one directory of the theme
+  fn candidates<'a>(
+    &'a self,
+    directory: &'a Directory,
+    icon: &'a str,
+  ) -> impl Iterator<Item = PathBuf> + 'a {
+    self.paths.iter().flat_map(move |path| {
+      EXTENSIONS.iter().map(move |extension| {
+        path
+          .join(&directory.name)
+          .join(format!("{}.{}", icon, extension))
+      })
+    })
+  }
+
+  // an exact match first, the closest size otherwise
+  fn lookup(&self, icon: &str, size: u32, scale: u32) -> Option<PathBuf> {
+    if let Some(path) = self
+      .directories
+      .iter()
+      .filter(|directory| directory.matches_size(size, scale))
+      .flat_map(|directory| self.candidates(directory, icon))
+      .find(|path| path.is_file())
+    {
+      return Some(path);
+    }
+
+    self
+      .directories
+      .iter()
+      .filter_map(|directory| {
+        self
+          .candidates(directory, icon)
+          .find(|path| path.is_file())
+          .map(|path| (directory.size_distance(size, scale), path))
+      })
+      .min_by_key(|(distance, _)| *distance)
+      .map(|(_, path)| path)
+  }
+}
+
+impl IconLoader {
+  pub fn new(theme: Option<&str>) -> Self {
+    let mut base_dirs = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+      base_dirs.push(home.join(".icons"));
+    }
+    if let Some(data) = dirs::data_dir() {
+      base_dirs.push(data.join("icons"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+      .ok()
+      .filter(|dirs| !dirs.is_empty())
+      .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+    base_dirs.extend(
+      data_dirs
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join("icons")),
+    );
+
+    Self {
+      base_dirs,
+      theme: String::from(theme.unwrap_or(DEFAULT_THEME)),
+      themes: HashMap::new(),
+      cache: HashMap::new(),
+    }
+  }
+
+  // the icon with the given name scaled to `size` pixels, cached after the first lookup
+  pub fn get(&mut self, name: &str, size: u32) -> Option<&Icon> {
+    let key = (String::from(name), size);
+    if !self.cache.contains_key(&key) {
+      let icon = self.find(name, size).and_then(|path| {
+        debug!("Icon {} at {}", name, path.display());
+        let icon = load(&path, size);
+        if icon.is_none() {
+          warn!("Failed to load the icon {}", path.display());
+        }
+        icon
+      });
+      self.cache.insert(key.clone(), icon);
+    }
+    self.cache.get(&key).and_then(Option::as_ref)
+  }
+
+  fn find(&mut self, name: &str, size: u32) -> Option<PathBuf> {
+    let theme = self.theme.clone();
+    let mut visited = Vec::new();
+    self
+      .find_in_theme(&theme, name, size, &mut visited)
+      .or_else(|| self.find_in_theme(DEFAULT_THEME, name, size, &mut visited))
+      .or_else(|| {
+        // icons without a theme
+        EXTENSIONS
+          .iter()
+          .map(|extension| Path::new("/usr/share/pixmaps").join(format!("{}.{}", name, extension)))
+          .find(|path| path.is_file())
+      })
+  }
+
+  // looks the icon up in the theme and in the themes it inherits from
+  fn find_in_theme(
+    &mut self,
+    theme: &str,
+    name: &str,
+    size: u32,
+    visited: &mut Vec<String>,
+  ) -> Option<PathBuf> {
+    if visited.iter().any(|v| v == theme) {
+      return None;
+    }
+    visited.push(String::from(theme));
+
+    let base_dirs = &self.base_dirs;
+    let loaded = self
+      .themes
+      .entry(String::from(theme))
+      .or_insert_with(|| Theme::load(theme, base_dirs))
+      .as_ref()?;
+
+    if let Some(path) = loaded.lookup(name, size, 1) {
+      return Some(path);
+    }
+
+    let parents = loaded.inherits.clone();
+    parents
+      .iter()
+      .find_map(|parent| self.find_in_theme(parent, name, size, visited))
+  }
+}
+
+// sections of key value pairs, enough for index.theme files
+fn parse_ini(source: &str) -> HashMap<String, HashMap<String, String>> {
+  let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+  let mut current = None;
+
+  for line in source.lines().map(str::trim) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+      current = Some(String::from(section));
+      sections.entry(String::from(section)).or_default();
+    } else if let (Some(section), Some((key, value))) = (current.as_ref(), line.split_once('=')) {
+      if let Some(entries) = sections.get_mut(section) {
+        entries
+          .entry(String::from(key.trim()))
+          .or_insert_with(|| String::from(value.trim()));
+      }
+    }
+  }
+
+  sections
+}
+
+fn load(path: &Path, size: u32) -> Option<Icon> {
+  match path.extension().and_then(|e| e.to_str()) {
+    Some("png") => load_png(path),
+    Some("svg") => load_svg(path, size),
+    _ => None,
+  }
+}
+
+pub fn load_png(path: &Path) -> Option<Icon> {
+  let mut decoder = png::Decoder::new(File::open(path).ok()?);
+  decoder.set_transformations(png::Transformations::normalize_to_color8());
+  let mut reader = decoder.read_info().ok()?;
+  let mut buffer = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buffer).ok()?;
+  let pixels = &buffer[..info.buffer_size()];
+
+  let rgba: Vec<[u8; 4]> = match info.color_type {
+    png::ColorType::Rgba => pixels
+      .chunks_exact(4)
+      .map(|p| [p[0], p[1], p[2], p[3]])
+      .collect(),
+    png::ColorType::Rgb => pixels
+      .chunks_exact(3)
+      .map(|p| [p[0], p[1], p[2], 0xFF])
+      .collect(),
+    png::ColorType::GrayscaleAlpha => pixels
+      .chunks_exact(2)
+      .map(|p| [p[0], p[0], p[0], p[1]])
+      .collect(),
+    png::ColorType::Grayscale => pixels.iter().map(|p| [*p, *p, *p, 0xFF]).collect(),
+    png::ColorType::Indexed => return None,
+  };
+
+  let premultiply = |c: u8, a: u8| (c as u32 * a as u32 / 0xFF) as u8;
+  Some(Icon {
+    width: info.width as i32,
+    height: info.height as i32,
+    data: rgba
+      .into_iter()
+      .map(|[r, g, b, a]| argb(premultiply(r, a), premultiply(g, a), premultiply(b, a), a))
+      .collect(),
+  })
+}
+
+// vector icons are rendered at the requested size right away
+fn load_svg(path: &Path, size: u32) -> Option<Icon> {
+  let data = std::fs::read(path).ok()?;
+  let mut tree = usvg::Tree::from_data(&data, &usvg::Options::default()).ok()?;
+  // the renderer skips nodes without bounding boxes
+  tree.calculate_abs_transforms();
+  tree.calculate_bounding_boxes();
+
+  let mut pixmap = Pixmap::new(size, size)?;
+  let scale = size as f32 / tree.size.width().max(tree.size.height());
+  resvg::render(
+    &tree,
+    Transform::from_scale(scale, scale),
+    &mut pixmap.as_mut(),
+  );
+
+  Some(Icon {
+    width: size as i32,
+    height: size as i32,
+    // tiny-skia is premultiplied already
+    data: pixmap
+      .data()
+      .chunks_exact(4)
+      .map(|p| argb(p[0], p[1], p[2], p[3]))
+      .collect(),
+  })
+}
+
+fn argb(r: u8, g: u8, b: u8, a: u8) -> u32 {
+  (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
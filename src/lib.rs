@@ -0,0 +1,25 @@
+//! The filtering, the usage statistic and the rendering of rmenu, independent of the Wayland
+//! frontend.
+//!
+//! [`AppContext`] holds the state of a menu made of a [`CommandList`] and its [`AppConfig`],
+//! [`AppContext::apply`] changes it by an [`Action`] and returns the [`Outcome`] for the frontend
+//! to react on, and a [`Renderer`] draws it into an ARGB buffer.
+pub mod action;
+pub mod command;
+pub mod config;
+pub mod context;
+pub mod renderer;
+
+mod check;
+mod color;
+mod icon;
+mod text;
+mod theme;
+
+pub use action::{Action, Key, Outcome};
+pub use command::{Command, CommandList};
+pub use config::{AppConfig, History, Options, StaticConfig};
+pub use context::AppContext;
+pub use renderer::{Region, Renderer};
+/// The YAML values of [`Options::overrides`] and [`config::override_value`]
+pub use serde_yaml;
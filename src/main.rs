@@ -1,15 +1,75 @@
 mod app;
-mod renderer;
-mod command;
-mod config;
+mod cli;
 mod menu;
+mod pinentry;
+mod render;
+mod replay;
 
 use app::App;
+use clap::Parser;
+use cli::{Args, Commands};
+use rmenu::config::{self, Options};
+
+// prints the problems of the config files and returns the exit code
+fn check_config(options: &Options) -> anyhow::Result<i32> {
+  if options.config_layers().is_empty() {
+    println!("No config file found, the defaults are used");
+  }
+
+  let report = options.check()?;
+  for file in &report.checked {
+    println!("Checked {}", file.display());
+  }
+  // the problems of the command line after the ones of the files
+  for diagnostic in &report.diagnostics {
+    println!("{}", diagnostic);
+  }
+  for error in &report.errors {
+    println!("{}", error);
+  }
+
+  Ok(if report.is_ok() { 0 } else { 1 })
+}
 
 fn main() -> anyhow::Result<()> {
   env_logger::init();
 
-  let config = config::parse().expect("A valid Config");
+  let mut args = Args::parse_from(cli::normalize_args(std::env::args()));
+  let options = args.options()?;
+
+  match args.command.take() {
+    Some(Commands::CheckConfig) => std::process::exit(check_config(&options)?),
+    Some(Commands::Render {
+      width,
+      height,
+      input,
+      selected,
+      entries,
+      output,
+    }) => {
+      return render::run(
+        config::load(options)?,
+        width,
+        height,
+        &input,
+        selected,
+        entries.as_deref(),
+        &output,
+      )
+    }
+    Some(Commands::Pinentry { .. }) => return pinentry::run(config::load(options)?),
+    None => {}
+  }
+
+  let entries = args.replay_entries.as_deref();
+  if let Some(keys) = args.replay.take() {
+    return replay::run(config::load(options)?, entries, replay::parse_keys(&keys)?);
+  }
+  if let Some(path) = args.replay_file.take() {
+    return replay::run(config::load(options)?, entries, replay::read_keys(&path)?);
+  }
+
+  let config = config::load(options)?;
 
-  App::new().run(config)
+  std::process::exit(App::new().run(config)?)
 }
@@ -1,4 +1,5 @@
-use log::{debug, info, warn};
+use anyhow::Context;
+use log::debug;
 use smithay_client_toolkit::{
   compositor::{CompositorHandler, CompositorState},
   delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_registry,
@@ -7,7 +8,7 @@ use smithay_client_toolkit::{
   registry::{ProvidesRegistryState, RegistryState},
   registry_handlers,
   seat::{
-    keyboard::{KeyboardHandler, Keysym},
+    keyboard::{KeyboardHandler, Keysym, Modifiers},
     Capability, SeatHandler, SeatState,
   },
   shell::wlr_layer::{LayerShell, LayerShellHandler},
@@ -24,9 +25,12 @@ use wayland_client::{
   Connection, QueueHandle,
 };
 
-use crate::{app::AppContext, command, renderer::Renderer};
+use rmenu::{
+  config::{self, ExclusiveZone, Position, Width, DEFAULT_HEIGHT},
+  Action, AppContext, Key, Outcome, Renderer,
+};
 
-static DEFAULT_HEIGHT: u32 = 32;
+static DEFAULT_CENTER_WIDTH: Width = Width::Pixels(600);
 
 pub struct Shell {
   registry_state: RegistryState,
@@ -34,13 +38,22 @@ pub struct Shell {
   output_state: OutputState,
   shm: Shm,
 
-  exit: bool,
+  // what closed the menu, left to the caller to act on
+  outcome: Option<Outcome>,
   pool: SlotPool,
   keyboard: Option<WlKeyboard>,
 
   size: (Option<u32>, Option<u32>),
+  edge: Anchor,
+  pending_width: Option<f32>,
+  close_on_unfocus: bool,
   layer: LayerSurface,
 
+  // the state changed since the last draw
+  dirty: bool,
+  // a frame callback was requested and not yet received
+  frame_pending: bool,
+
   renderer: Renderer,
   context: AppContext,
 }
@@ -53,7 +66,10 @@ impl CompositorHandler for Shell {
     _surface: &wl_surface::WlSurface,
     _time: u32,
   ) {
-    self.draw(qh)
+    self.frame_pending = false;
+    if self.dirty {
+      self.draw(qh);
+    }
   }
 
   fn scale_factor_changed(
@@ -112,7 +128,7 @@ impl LayerShellHandler for Shell {
     _qh: &QueueHandle<Self>,
     _layer: &smithay_client_toolkit::shell::wlr_layer::LayerSurface,
   ) {
-    self.exit = true;
+    self.outcome = Some(Outcome::Cancelled);
   }
 
   fn configure(
@@ -123,6 +139,19 @@ impl LayerShellHandler for Shell {
     configure: smithay_client_toolkit::shell::wlr_layer::LayerSurfaceConfigure,
     _serial: u32,
   ) {
+    if let Some(percent) = self.pending_width.take() {
+      if configure.new_size.0 > 0 {
+        // shrink to the requested share of the full width and wait for the next configure
+        let width = (configure.new_size.0 as f32 * percent / 100.).round() as u32;
+        self.layer.set_anchor(self.edge);
+        self
+          .layer
+          .set_size(width.max(1), self.size.1.unwrap_or(DEFAULT_HEIGHT));
+        self.layer.commit();
+        return;
+      }
+    }
+
     let new_size = (Some(configure.new_size.0), Some(configure.new_size.1));
     if self.size != new_size {
       self.size = new_size;
@@ -206,17 +235,23 @@ impl KeyboardHandler for Shell {
     _surface: &wl_surface::WlSurface,
     _serial: u32,
   ) {
+    if self.close_on_unfocus {
+      debug!("Lost keyboard focus, closing");
+      self.outcome = Some(Outcome::Cancelled);
+    }
   }
 
   fn press_key(
     &mut self,
     _conn: &Connection,
-    _qh: &QueueHandle<Self>,
+    qh: &QueueHandle<Self>,
     _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
     _serial: u32,
     event: smithay_client_toolkit::seat::keyboard::KeyEvent,
   ) {
-    self.handle_key(event);
+    if self.handle_key(event) {
+      self.request_redraw(qh);
+    }
   }
 
   fn release_key(
@@ -244,9 +279,8 @@ impl KeyboardHandler for Shell {
     _qh: &QueueHandle<Self>,
     _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
     _serial: u32,
-    modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
+    _modifiers: Modifiers,
   ) {
-    self.context.modifiers = modifiers;
   }
 
   fn update_repeat_info(
@@ -265,41 +299,114 @@ impl ShmHandler for Shell {
   }
 }
 
+// the globals the menu cannot be shown without
+fn bind(
+  globals: &GlobalList,
+  qh: &QueueHandle<Shell>,
+) -> anyhow::Result<(CompositorState, LayerShell, Shm, SlotPool)> {
+  // The compositor (not to be confused with the server which is commonly called the compositor) allows
+  // configuring surfaces to be presented.
+  let compositor = CompositorState::bind(globals, qh).context("wl_compositor is not available")?;
+
+  // This app uses the wlr layer shell, which may not be available with every compositor.
+  let layer_shell = LayerShell::bind(globals, qh).context("layer shell is not available")?;
+
+  // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
+  // we share with the compositor process.
+  let shm = Shm::bind(globals, qh).context("wl_shm is not available")?;
+
+  // We don't know how large the window will be yet, so lets assume the minimum size we suggested for the
+  // initial memory allocation.
+  let pool = SlotPool::new(256 * 256 * 4, &shm).context("Failed to create pool")?;
+
+  Ok((compositor, layer_shell, shm, pool))
+}
+
 impl Shell {
-  pub fn new(app_context: AppContext, globals: GlobalList, qh: QueueHandle<Shell>) -> Self {
-    let height = app_context
-      .config
-      .static_config
-      .style
-      .as_ref()
-      .map(|s| s.height)
-      .unwrap_or(DEFAULT_HEIGHT);
-
-    // The compositor (not to be confused with the server which is commonly called the compositor) allows
-    // configuring surfaces to be presented.
-    let compositor = CompositorState::bind(&globals, &qh).expect("wl_compositor is not available");
-
-    // This app uses the wlr layer shell, which may not be available with every compositor.
-    let layer_shell = LayerShell::bind(&globals, &qh).expect("layer shell is not available");
+  // the context comes back along with the error if a global is missing, it is moved out once
+  // per menu so its size does not matter
+  #[allow(clippy::result_large_err)]
+  pub fn new(
+    app_context: AppContext,
+    globals: GlobalList,
+    qh: QueueHandle<Shell>,
+  ) -> Result<Self, (AppContext, anyhow::Error)> {
+    let (compositor, layer_shell, shm, pool) = match bind(&globals, &qh) {
+      Ok(bound) => bound,
+      Err(e) => return Err((app_context, e)),
+    };
+
+    let static_config = &app_context.config().static_config;
+    let style = static_config.style.as_ref();
+    let behavior = static_config.surface.as_ref();
+
+    let height = static_config.height();
+
+    let position = static_config.position();
+    let width = style.and_then(|s| s.width).or(match position {
+      Position::Center => Some(DEFAULT_CENTER_WIDTH),
+      _ => None,
+    });
 
     // A layer surface is created from a surface.
     let surface = compositor.create_surface(&qh);
 
     // And then we create the layer shell.
-    let layer =
-      layer_shell.create_layer_surface(&qh, surface, Layer::Top, Some("menu_layer"), None);
-
-    // request to expand to the surface edges ignoring exlusive zones
-    layer.set_exclusive_zone(-1);
-
-    // set hight and leave widht 0 to stretch the whole screen in configure
-    layer.set_size(0, height);
+    let layer = layer_shell.create_layer_surface(
+      &qh,
+      surface,
+      match behavior.and_then(|s| s.layer).unwrap_or_default() {
+        config::Layer::Background => Layer::Background,
+        config::Layer::Bottom => Layer::Bottom,
+        config::Layer::Top => Layer::Top,
+        config::Layer::Overlay => Layer::Overlay,
+      },
+      Some("menu_layer"),
+      None,
+    );
+
+    layer.set_exclusive_zone(
+      match style.and_then(|s| s.exclusive_zone).unwrap_or_default() {
+        // request to expand to the surface edges ignoring exlusive zones
+        ExclusiveZone::Ignore => -1,
+        // stay out of the exclusive zones of others (e.g. a status bar)
+        ExclusiveZone::Respect => 0,
+        // and reserve our own space as well
+        ExclusiveZone::Reserve => height as i32,
+      },
+    );
+
+    let edge = match position {
+      Position::Top => Anchor::TOP,
+      Position::Bottom => Anchor::BOTTOM,
+      // without any anchor the compositor centers the surface
+      Position::Center => Anchor::empty(),
+    };
+
+    if let Some(Width::Pixels(width)) = width {
+      // a fixed width gets centered at the top or bottom edge or in the middle of the screen
+      layer.set_size(width, height);
+      layer.set_anchor(edge);
+    } else {
+      // set hight and leave widht 0 to stretch the whole screen in configure,
+      // a percentage is resolved in the first configure that reports the full width
+      layer.set_size(0, height);
+      layer.set_anchor(edge | Anchor::LEFT | Anchor::RIGHT);
+    }
 
-    // Anchor to the top left/right corner of the output
-    layer.set_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT);
+    if let Some(margin) = style.and_then(|s| s.margin) {
+      let (top, right, bottom, left) = margin.sides();
+      layer.set_margin(top, right, bottom, left);
+    }
 
-    // request exclusive keyboard events for our layer
-    layer.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+    // request keyboard events for our layer, either exclusively or when focused by the compositor
+    let keyboard_interactivity = behavior
+      .and_then(|s| s.keyboard_interactivity)
+      .unwrap_or_default();
+    layer.set_keyboard_interactivity(match keyboard_interactivity {
+      config::KeyboardInteractivity::Exclusive => KeyboardInteractivity::Exclusive,
+      config::KeyboardInteractivity::OnDemand => KeyboardInteractivity::OnDemand,
+    });
 
     // In order for the layer surface to be mapped, we need to perform an initial commit with no attached\
     // buffer. For more info, see WaylandSurface::commit
@@ -308,53 +415,79 @@ impl Shell {
     // surface with the correct options.
     layer.commit();
 
-    // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
-    // we share with the compositor process.
-    let shm = Shm::bind(&globals, &qh).expect("wl_shm is not available");
-
-    // We don't know how large the window will be yet, so lets assume the minimum size we suggested for the
-    // initial memory allocation.
-    let pool = SlotPool::new(256 * 256 * 4, &shm).expect("Failed to create pool");
-
-    Self {
+    Ok(Self {
       registry_state: RegistryState::new(&globals),
       seat_state: SeatState::new(&globals, &qh),
       output_state: OutputState::new(&globals, &qh),
-      renderer: Renderer::new(&app_context.config.static_config),
-      exit: false,
+      renderer: Renderer::new(&app_context.config().static_config),
+      outcome: None,
       keyboard: None,
       shm,
       pool,
       size: (None, Some(height)),
+      edge,
+      close_on_unfocus: keyboard_interactivity == config::KeyboardInteractivity::OnDemand
+        && behavior.and_then(|s| s.close_on_unfocus).unwrap_or(false),
+      pending_width: match width {
+        Some(Width::Percent(percent)) => Some(percent),
+        _ => None,
+      },
       layer,
+      dirty: false,
+      frame_pending: false,
       context: app_context,
-    }
+    })
   }
 
   pub fn about_to_exit(&self) -> bool {
-    self.exit
+    self.outcome.is_some()
+  }
+
+  // the state of the menu and what closed it, the surface goes away with the shell
+  pub fn finish(self) -> (AppContext, Outcome) {
+    (self.context, self.outcome.unwrap_or(Outcome::Cancelled))
+  }
+
+  // draws right away or with the next frame callback if the compositor did not catch up yet
+  fn request_redraw(&mut self, qh: &QueueHandle<Self>) {
+    self.dirty = true;
+    if !self.frame_pending && self.size.0.is_some() {
+      self.draw(qh);
+    }
   }
 
   pub fn draw(&mut self, qh: &QueueHandle<Self>) {
+    self.dirty = false;
+
     let width = self.size.0.unwrap_or(0) as i32;
     let height = self.size.1.unwrap_or(0) as i32;
-    let stride = width * 4 as i32;
+    let stride = width * 4;
 
     let (buffer, canvas) = self
       .pool
       .create_buffer(width, height, stride, wl_shm::Format::Argb8888)
       .expect("create buffer");
 
-    self.renderer.render(&self.context, width, height, canvas);
+    let damage = self.renderer.render(&self.context, width, height, canvas);
+    if damage.is_empty() {
+      debug!("Nothing changed, skip the commit");
+      return;
+    }
 
-    // Damage the entire window
-    self.layer.wl_surface().damage_buffer(0, 0, width, height);
+    // Damage only the regions that changed since the last frame
+    for region in damage {
+      self
+        .layer
+        .wl_surface()
+        .damage_buffer(region.x, region.y, region.width, region.height);
+    }
 
-    // Request our next frame
+    // Throttle further redraws until the compositor presented this one
     self
       .layer
       .wl_surface()
       .frame(qh, self.layer.wl_surface().clone());
+    self.frame_pending = true;
 
     // Attach and commit to present
     buffer
@@ -363,67 +496,50 @@ impl Shell {
     self.layer.commit();
   }
 
-  fn handle_key(&mut self, event: smithay_client_toolkit::seat::keyboard::KeyEvent) {
-    debug!("Key press: {event:?}");
-    match event.keysym {
-      Keysym::Escape => {
-        self.exit = true;
-      }
-      Keysym::BackSpace => {
-        // pop one char
-        self.context.pop_and_filter();
+  // returns true if the state changed and the menu has to be redrawn
+  fn handle_key(&mut self, event: smithay_client_toolkit::seat::keyboard::KeyEvent) -> bool {
+    let action = key_action(&event);
+    // no key of a password must end up in the logs, only the actions other than text are named
+    if self.context.config().static_config.password() {
+      match &action {
+        Some(Action::InsertText(_)) => debug!("Key press: text"),
+        Some(action) => debug!("Key press: {:?}", action),
+        None => debug!("Not handled key"),
       }
-      Keysym::Tab => {
-        if self.context.current_index < self.context.list.filtered_len() {
-          // shift index right
-          self.context.current_index += 1;
-        }
+    } else {
+      debug!("Key press: {event:?}");
+      if action.is_none() {
+        debug!("Not handled KEY {:?}", event.keysym);
       }
-      Keysym::ISO_Left_Tab => {
-        if self.context.current_index > 0 {
-          // shift index left
-          self.context.current_index -= 1;
-        }
-      }
-      Keysym::Return => {
-        let (exit, binary) = if let Some(target) = self.context.target() {
-          info!("Execute {}", target);
-
-          // launch
-          (command::launch(target), Some(target.binary()))
-          //(0, None)
-        } else {
-          // exit with failure (no target)
-          (10, None)
-        };
-
-        // write history
-        if let Some(binary) = binary {
-          if let Err(e) = self.context.config.increment_and_store_history(binary) {
-            warn!("Failed to store history data - {}", e);
-          }
-        }
-
-        std::process::exit(exit);
+    }
+    let Some(action) = action else {
+      return false;
+    };
+
+    match self.context.apply(action) {
+      Outcome::Unchanged => false,
+      Outcome::Changed => true,
+      outcome => {
+        self.outcome = Some(outcome);
+        false
       }
-      _ => match event.utf8 {
-        Some(txt) => {
-          debug!(" -> Received text `{}`", txt);
-
-          // append and apply the filter
-          self.context.append_and_filter(txt.as_str());
-
-          // reset current index
-          self.context.current_index = 0;
-        }
-        _ => {
-          debug!("Not handled KEY {:?}", event.utf8);
-        }
-      },
     }
   }
 }
 
+// the action a key stands for
+fn key_action(event: &smithay_client_toolkit::seat::keyboard::KeyEvent) -> Option<Action> {
+  let key = match event.keysym {
+    Keysym::Escape => Key::Escape,
+    Keysym::BackSpace => Key::BackSpace,
+    Keysym::Tab => Key::Tab,
+    Keysym::ISO_Left_Tab => Key::LeftTab,
+    Keysym::Return => Key::Return,
+    _ => Key::Text(event.utf8.clone()?),
+  };
+  Action::from_key(key)
+}
+
 delegate_compositor!(Shell);
 delegate_output!(Shell);
 delegate_shm!(Shell);
@@ -0,0 +1,359 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use log::{debug, error, info};
+
+use rmenu::{config::AppConfig, AppContext, CommandList, Outcome};
+
+use crate::app::App;
+
+// gpg error codes with the pinentry as error source
+static ERR_GENERAL: &str = "ERR 83886081 General error <Pinentry>";
+static ERR_CANCELED: &str = "ERR 83886179 Operation cancelled <Pinentry>";
+static ERR_NOT_CONFIRMED: &str = "ERR 83886194 Not confirmed <Pinentry>";
+static ERR_UNKNOWN_COMMAND: &str = "ERR 83886355 Unknown IPC command <Pinentry>";
+static ERR_PARAMETER: &str = "ERR 83886360 IPC parameter error <Pinentry>";
+
+static DEFAULT_PROMPT: &str = "PIN:";
+static DEFAULT_OK: &str = "OK";
+static DEFAULT_CANCEL: &str = "Cancel";
+static DEFAULT_REPEAT: &str = "Repeat:";
+static DEFAULT_REPEAT_ERROR: &str = "Passphrases do not match";
+
+// the texts set by the client for the next dialog
+#[derive(Default)]
+struct Dialog {
+  description: Option<String>,
+  prompt: Option<String>,
+  error: Option<String>,
+  ok: Option<String>,
+  cancel: Option<String>,
+  // ask a second time and compare when set
+  repeat: Option<String>,
+  repeat_error: Option<String>,
+}
+
+impl Dialog {
+  // the error and the description in a single line, shown while the input is empty
+  fn placeholder(&self) -> Option<String> {
+    let text = [self.error.as_deref(), self.description.as_deref()]
+      .into_iter()
+      .flatten()
+      .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+      .filter(|text| !text.is_empty())
+      .collect::<Vec<_>>()
+      .join(" - ");
+    (!text.is_empty()).then_some(text)
+  }
+}
+
+// the button labels come with an underscore in front of the mnemonic
+fn label(text: Option<&str>, default: &str) -> String {
+  match text {
+    Some(text) if !text.is_empty() => text.replace('_', ""),
+    _ => String::from(default),
+  }
+}
+
+// decodes the %XX escapes of an argument
+fn unescape(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let hex = bytes
+      .get(index + 1..index + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match (bytes[index], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        index += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        index += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// escapes the characters a data line must not contain
+fn escape(text: &str) -> String {
+  text
+    .replace('%', "%25")
+    .replace('\r', "%0D")
+    .replace('\n', "%0A")
+}
+
+struct Pinentry {
+  // taken by the menu while it is shown
+  config: Option<AppConfig>,
+  dialog: Dialog,
+}
+
+impl Pinentry {
+  // shows the menu with the entries, or a hidden input without any, and returns what closed it
+  fn show(
+    &mut self,
+    prompt: String,
+    placeholder: Option<String>,
+    entries: &[String],
+  ) -> Result<Outcome> {
+    let mut config = self.config.take().expect("Config to be available");
+    let password = entries.is_empty();
+    config.static_config.prompt = Some(format!("{} ", prompt));
+    config.static_config.placeholder = placeholder;
+    config.static_config.password = Some(password);
+
+    let list = CommandList::from_paths(&config, entries.iter().map(Into::into).collect());
+    let (context, outcome) = App::new().show(AppContext::with_list(config, list));
+    self.config = Some(context.into_config());
+
+    outcome
+  }
+
+  fn get_pin(&mut self) -> Result<Option<String>> {
+    let prompt = self
+      .dialog
+      .prompt
+      .clone()
+      .unwrap_or_else(|| String::from(DEFAULT_PROMPT));
+    let mut placeholder = self.dialog.placeholder();
+
+    loop {
+      let Outcome::Submitted(pin) = self.show(prompt.clone(), placeholder.clone(), &[])? else {
+        return Ok(None);
+      };
+      let Some(repeat) = self.dialog.repeat.clone() else {
+        return Ok(Some(pin));
+      };
+      match self.show(repeat, None, &[])? {
+        Outcome::Submitted(repeated) if repeated == pin => return Ok(Some(pin)),
+        Outcome::Submitted(_) => {
+          placeholder = Some(
+            self
+              .dialog
+              .repeat_error
+              .clone()
+              .unwrap_or_else(|| String::from(DEFAULT_REPEAT_ERROR)),
+          )
+        }
+        _ => return Ok(None),
+      }
+    }
+  }
+
+  fn confirm(&mut self, one_button: bool) -> Result<bool> {
+    let ok = label(self.dialog.ok.as_deref(), DEFAULT_OK);
+    let mut entries = vec![ok.clone()];
+    if !one_button {
+      entries.push(label(self.dialog.cancel.as_deref(), DEFAULT_CANCEL));
+    }
+    let prompt = self.dialog.placeholder().unwrap_or_default();
+
+    Ok(match self.show(prompt, None, &entries)? {
+      Outcome::Accepted(command) => command.name == ok,
+      _ => false,
+    })
+  }
+
+  // handles a single command and writes the response, a failure is answered with an error and
+  // the session goes on
+  fn handle(&mut self, line: &str, out: &mut impl Write) -> std::io::Result<bool> {
+    match self.respond(line, out) {
+      Ok(open) => Ok(open),
+      Err(e) => {
+        error!("Pinentry command failed - {:#}", e);
+        writeln!(out, "{}", ERR_GENERAL)?;
+        Ok(true)
+      }
+    }
+  }
+
+  fn respond(&mut self, line: &str, out: &mut impl Write) -> Result<bool> {
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let text = Some(unescape(argument)).filter(|text| !text.is_empty());
+    // never log the arguments, they may contain secrets
+    debug!("Pinentry command {}", command);
+
+    match command.to_uppercase().as_str() {
+      "SETDESC" => self.dialog.description = text,
+      "SETPROMPT" => self.dialog.prompt = text,
+      "SETERROR" => self.dialog.error = text,
+      "SETOK" => self.dialog.ok = text,
+      "SETCANCEL" => self.dialog.cancel = text,
+      "SETREPEAT" => {
+        self.dialog.repeat = Some(text.unwrap_or_else(|| String::from(DEFAULT_REPEAT)))
+      }
+      "SETREPEATERROR" => self.dialog.repeat_error = text,
+      // nothing to show them on
+      "SETTITLE" | "SETNOTOK" | "SETKEYINFO" | "SETQUALITYBAR" | "SETQUALITYBAR_TT"
+      | "SETGENPIN" | "SETGENPIN_TT" | "SETTIMEOUT" | "OPTION" | "NOP" => {}
+      "GETINFO" => match argument {
+        "flavor" => writeln!(out, "D rmenu")?,
+        "version" => writeln!(out, "D {}", env!("CARGO_PKG_VERSION"))?,
+        "pid" => writeln!(out, "D {}", std::process::id())?,
+        _ => {
+          writeln!(out, "{}", ERR_PARAMETER)?;
+          return Ok(true);
+        }
+      },
+      "GETPIN" => {
+        let pin = self.get_pin()?;
+        // the error is only shown once
+        self.dialog.error = None;
+        match pin {
+          Some(pin) => {
+            if self.dialog.repeat.is_some() {
+              writeln!(out, "S PIN_REPEATED")?;
+            }
+            if !pin.is_empty() {
+              writeln!(out, "D {}", escape(&pin))?;
+            }
+          }
+          None => {
+            writeln!(out, "{}", ERR_CANCELED)?;
+            return Ok(true);
+          }
+        }
+      }
+      "CONFIRM" | "MESSAGE" => {
+        let one_button = command.eq_ignore_ascii_case("MESSAGE") || argument == "--one-button";
+        if !self.confirm(one_button)? && !one_button {
+          writeln!(out, "{}", ERR_NOT_CONFIRMED)?;
+          return Ok(true);
+        }
+      }
+      "RESET" => self.dialog = Dialog::default(),
+      "BYE" => {
+        writeln!(out, "OK closing connection")?;
+        return Ok(false);
+      }
+      _ => {
+        writeln!(out, "{}", ERR_UNKNOWN_COMMAND)?;
+        return Ok(true);
+      }
+    }
+
+    writeln!(out, "OK")?;
+    Ok(true)
+  }
+}
+
+// speaks the Assuan pinentry protocol on stdin and stdout until the client says BYE or hangs up
+pub fn run(mut config: AppConfig) -> Result<()> {
+  // the buttons are no binaries to weight by the usage statistic
+  config.history = Default::default();
+
+  let mut pinentry = Pinentry {
+    config: Some(config),
+    dialog: Dialog::default(),
+  };
+
+  let stdin = std::io::stdin();
+  let mut out = std::io::stdout().lock();
+  writeln!(out, "OK Pleased to meet you")?;
+  out.flush()?;
+
+  for line in stdin.lock().lines() {
+    let line = line?;
+    let line = line.trim_end();
+    // comments and empty lines are allowed by the protocol
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let open = pinentry.handle(line, &mut out)?;
+    out.flush()?;
+    if !open {
+      break;
+    }
+  }
+
+  info!("Pinentry closed");
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use rmenu::config::StaticConfig;
+
+  use super::*;
+
+  fn pinentry() -> Pinentry {
+    Pinentry {
+      config: Some(AppConfig::from(StaticConfig::default())),
+      dialog: Dialog::default(),
+    }
+  }
+
+  // the response to the command and whether the session stays open
+  fn handle(pinentry: &mut Pinentry, line: &str) -> (String, bool) {
+    let mut out = Vec::new();
+    let open = pinentry.handle(line, &mut out).unwrap();
+    (String::from_utf8(out).unwrap(), open)
+  }
+
+  #[test]
+  fn unescapes_the_arguments() {
+    assert_eq!(unescape("50%25 done%0Anext"), "50% done\nnext");
+    assert_eq!(unescape("%C3%A4"), "\u{e4}");
+    // incomplete or invalid escapes are kept as they are
+    assert_eq!(unescape("100%"), "100%");
+    assert_eq!(unescape("%zz%4"), "%zz%4");
+  }
+
+  #[test]
+  fn escapes_the_data_lines() {
+    assert_eq!(escape("a%b\r\nc"), "a%25b%0D%0Ac");
+    assert_eq!(unescape(&escape("50% done\n")), "50% done\n");
+  }
+
+  #[test]
+  fn sets_the_description() {
+    let mut pinentry = pinentry();
+    assert_eq!(
+      handle(&mut pinentry, "SETDESC Unlock%0Athe key"),
+      (String::from("OK\n"), true)
+    );
+    assert_eq!(
+      pinentry.dialog.description.as_deref(),
+      Some("Unlock\nthe key")
+    );
+
+    handle(&mut pinentry, "SETDESC");
+    assert_eq!(pinentry.dialog.description, None);
+  }
+
+  #[test]
+  fn answers_getinfo() {
+    let mut pinentry = pinentry();
+    assert_eq!(
+      handle(&mut pinentry, "GETINFO flavor"),
+      (String::from("D rmenu\nOK\n"), true)
+    );
+    assert_eq!(
+      handle(&mut pinentry, "GETINFO version"),
+      (format!("D {}\nOK\n", env!("CARGO_PKG_VERSION")), true)
+    );
+    assert_eq!(
+      handle(&mut pinentry, "GETINFO ttyinfo"),
+      (format!("{}\n", ERR_PARAMETER), true)
+    );
+  }
+
+  #[test]
+  fn rejects_unknown_commands() {
+    let mut pinentry = pinentry();
+    assert_eq!(
+      handle(&mut pinentry, "FOO bar"),
+      (format!("{}\n", ERR_UNKNOWN_COMMAND), true)
+    );
+    assert_eq!(
+      handle(&mut pinentry, "BYE"),
+      (String::from("OK closing connection\n"), false)
+    );
+  }
+}
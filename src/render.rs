@@ -0,0 +1,108 @@
+use std::{
+  fs::File,
+  io::BufWriter,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::info;
+
+use rmenu::{Action, AppConfig, AppContext, CommandList, Renderer};
+
+// the same entries on every machine, so the images only change with the config
+static SAMPLE_ENTRIES: [&str; 16] = [
+  "/usr/bin/alacritty",
+  "/usr/bin/chromium",
+  "/usr/bin/emacs",
+  "/usr/bin/files",
+  "/usr/bin/firefox",
+  "/usr/bin/fish",
+  "/usr/bin/foot",
+  "/usr/bin/gimp",
+  "/usr/bin/htop",
+  "/usr/bin/inkscape",
+  "/usr/bin/kitty",
+  "/usr/bin/mpv",
+  "/usr/bin/nautilus",
+  "/usr/bin/thunderbird",
+  "/usr/bin/vim",
+  "/usr/bin/vlc",
+];
+
+// one entry per line, either a name or a path
+pub fn read_entries(path: &Path) -> Result<Vec<PathBuf>> {
+  let source = std::fs::read_to_string(path)
+    .with_context(|| format!("Failed to read the entries {}", path.display()))?;
+  Ok(
+    source
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty())
+      .map(PathBuf::from)
+      .collect(),
+  )
+}
+
+// the premultiplied BGRA pixels of the canvas as straight RGBA
+fn to_rgba(canvas: &[u8]) -> Vec<u8> {
+  canvas
+    .chunks_exact(4)
+    .flat_map(|pixel| {
+      let (b, g, r, a) = (pixel[0], pixel[1], pixel[2], pixel[3]);
+      let straight = |c: u8| match a {
+        0 => 0,
+        a => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+      };
+      [straight(r), straight(g), straight(b), a]
+    })
+    .collect()
+}
+
+// renders a single frame of the menu offscreen and writes it to a PNG file
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+  mut config: AppConfig,
+  width: i32,
+  height: Option<i32>,
+  input: &str,
+  selected: usize,
+  entries: Option<&Path>,
+  output: &Path,
+) -> Result<()> {
+  // the usage statistic would change the order of the entries
+  config.history = Default::default();
+
+  let entries = match entries {
+    Some(path) => read_entries(path)?,
+    None => SAMPLE_ENTRIES.iter().map(PathBuf::from).collect(),
+  };
+  let width = width.max(1);
+  let height = height
+    .unwrap_or(config.static_config.height() as i32)
+    .max(1);
+
+  let list = CommandList::from_paths(&config, entries);
+  let mut context = AppContext::with_list(config, list);
+  if !input.is_empty() {
+    context.apply(Action::InsertText(String::from(input)));
+  }
+  context.select(selected);
+
+  let mut renderer = Renderer::new(&context.config().static_config);
+  let mut canvas = vec![0u8; (width * height * 4) as usize];
+  renderer.render(&context, width, height, &mut canvas);
+
+  let file = File::create(output)
+    .with_context(|| format!("Failed to create the image {}", output.display()))?;
+  let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder
+    .write_header()?
+    .write_image_data(&to_rgba(&canvas))
+    .with_context(|| format!("Failed to write the image {}", output.display()))?;
+
+  info!("Rendered {}x{} to {}", width, height, output.display());
+
+  Ok(())
+}
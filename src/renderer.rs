@@ -1,16 +1,36 @@
 use std::sync::Arc;
 
-use font_kit::{font::Font, source::SystemSource};
-
-use log::debug;
-use raqote::{DrawOptions, DrawTarget, Point, SolidSource, Source};
+use log::{debug, warn};
+use raqote::{
+  DrawOptions, DrawTarget, ExtendMode, FilterMode, Image, IntPoint, IntRect, Mask,
+  Path as DrawPath, PathBuilder, Point, SolidSource, Source, Spread, StrokeStyle, Transform,
+};
 
-use crate::{app::AppContext, config::{Color, StaticConfig}};
+use crate::{
+  command::Command,
+  config::{
+    Color, Element, Gradient, GradientKind, GradientStop, ImageMode, Position, StaticConfig, Width,
+  },
+  context::AppContext,
+  icon::{self, Icon, IconLoader},
+  text::{FontFace, FontSet, SUBPIXELS},
+};
 
-static DEFAULT_FONT: &'static [u8; 42756] = include_bytes!("../assets/ShareTechMono-Regular.ttf");
+static DEFAULT_FONT: &[u8; 42756] = include_bytes!("../assets/ShareTechMono-Regular.ttf");
 
 static DEFAULT_FONT_SIZE: f32 = 24.;
 static DEFAULT_FONT_SPACING: f32 = 2.;
+static DEFAULT_PROMPT: &str = "> ";
+
+static DEFAULT_ELEMENTS: [Element; 3] = [Element::Prompt, Element::Input, Element::List];
+static DEFAULT_INPUT_WIDTH: Width = Width::Pixels(200);
+
+static ELEMENT_SPACING: f32 = 20.;
+static ITEM_SPACING: f32 = 15.;
+static ICON_SPACING: f32 = 8.;
+static ELLIPSIS: &str = "…";
+// shown for every character of a password
+static PASSWORD_BULLET: &str = "•";
 
 static DEFAULT_HIGHLIGHT: Color = Color {
   r: 0xFF,
@@ -19,6 +39,13 @@ static DEFAULT_HIGHLIGHT: Color = Color {
   a: 0xFF,
 };
 
+static DEFAULT_MATCH: Color = Color {
+  r: 0xFF,
+  g: 0xB0,
+  b: 0x00,
+  a: 0xFF,
+};
+
 static DEFAULT_FOREGROUND: Color = Color {
   r: 0xD0,
   g: 0xD0,
@@ -33,82 +60,329 @@ static DEFAULT_BACKGROUND: Color = Color {
   a: 0xEE,
 };
 
+static DEFAULT_BORDER: Color = Color {
+  r: 0x60,
+  g: 0x60,
+  b: 0x60,
+  a: 0xFF,
+};
+
+// an entry of the horizontal list laid out for its page
+struct Item {
+  label: String,
+  icon: bool,
+  label_offset: f32,
+  width: f32,
+}
+
+// how the characters matched by the input are marked
+#[derive(Clone, Copy)]
+struct MatchStyle {
+  color: Option<SolidSource>,
+  underline: bool,
+}
+
+// the byte offsets of the matched characters of a label
+struct Matches<'a> {
+  offsets: &'a [usize],
+  style: MatchStyle,
+}
+
+// the part of the surface inside the border
+#[derive(Clone, Copy)]
+struct Area {
+  x: f32,
+  y: f32,
+  width: f32,
+  height: f32,
+}
+
+// the direction of a separator line
+#[derive(Clone, Copy)]
+enum Orientation {
+  Horizontal,
+  Vertical,
+}
+
+// what a frame shows, compared with the last one to find the parts to draw again
+#[derive(Clone, PartialEq)]
+struct FrameState {
+  width: i32,
+  height: i32,
+  input: String,
+  filtered: usize,
+  current_index: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Redraw {
+  All,
+  // only the selection moved away from the given index, the input row stays as it is
+  Selection(usize),
+}
+
 struct RendererContext {
   highlight: SolidSource,
   foreground: SolidSource,
   background: SolidSource,
+  gradient: Option<Gradient>,
+  image: Option<(Icon, ImageMode)>,
+  selected_background: Option<SolidSource>,
+  selected_padding: f32,
+  selected_radius: f32,
+  selected_border: Option<(SolidSource, f32)>,
+  row_padding: f32,
+  separator: Option<(SolidSource, f32)>,
+  matches: MatchStyle,
+  border: SolidSource,
+  border_width: f32,
+  corner_radius: f32,
+  prompt: String,
+  placeholder: Option<String>,
+  placeholder_color: SolidSource,
+  elements: Vec<Element>,
+  input_width: Width,
+  descriptions: bool,
+  // the input is hidden and the entries are not shown
+  password: bool,
+  description_color: SolidSource,
+  position: Position,
+  lines: u32,
   font_spacing: f32,
   font_size: f32,
-  font: Font,
+  fonts: FontSet,
+  icons: Option<IconLoader>,
 }
 
+/// Draws a menu into an ARGB buffer, keeping the last frame to draw only what changed
 pub struct Renderer {
   context: RendererContext,
   cursor: Option<f32>,
+  // the state of the last frame, which is kept in the target
+  last: Option<FrameState>,
+  // the space left for the list of the bar in the last frame
+  list_span: (f32, f32),
+  // the first entry of the page of the bar or the vertical list in the last frame
+  page_start: usize,
+  // reused as long as the size stays the same
+  target: Option<DrawTarget>,
+  background: Option<DrawTarget>,
+  // the pixels outside of the rounded corners, they are restored after drawing as the glyphs are
+  // blitted without the clip
+  corners: Vec<usize>,
+  // the regions of a partial frame are drawn here first, the glyphs are not clipped
+  scratch: Option<DrawTarget>,
 }
 
-impl Into<SolidSource> for Color {
-  fn into(self) -> SolidSource {
+/// A part of the surface in buffer coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+  pub x: i32,
+  pub y: i32,
+  pub width: i32,
+  pub height: i32,
+}
+
+impl From<Color> for SolidSource {
+  fn from(color: Color) -> SolidSource {
     SolidSource {
-      r: self.r,
-      g: self.g,
-      b: self.b,
-      a: self.a,
+      r: color.r,
+      g: color.g,
+      b: color.b,
+      a: color.a,
     }
   }
 }
 
 impl Renderer {
   pub fn new(config: &StaticConfig) -> Renderer {
-    let default_font = font_kit::font::Font::from_bytes(
-      Arc::new(DEFAULT_FONT.to_vec()), 
-      0
-    ).ok();
+    let default_font = || FontFace::from_bytes(Arc::new(DEFAULT_FONT.to_vec()), 0);
+
+    let primary = config.font.as_ref().and_then(|font| {
+      let o1 = font.path.as_deref().and_then(FontFace::from_path);
+
+      let o2 = font.name.as_deref().and_then(|name| {
+        debug!("{}", name);
+        FontFace::by_name(name)
+      });
+
+      debug!(
+        "FONT path {:?}, name {:?}",
+        o1.as_ref().map(|f| &f.font),
+        o2.as_ref().map(|f| &f.font)
+      );
+
+      o1.or(o2)
+    });
 
-    let font = config
+    let fallback = config
       .font
       .as_ref()
-      .and_then(|font| {
-        let o1 = font.path.as_ref().and_then(|path| {
-          std::fs::read(path)
-            .ok()
-            .and_then(|data| font_kit::font::Font::from_bytes(Arc::new(data), 0).ok())
-        });
-
-        let o2 = font.name.as_ref().and_then(|name| {
-          debug!("{}", name);
-          SystemSource::new()
-            .select_by_postscript_name(&name)
-            .ok()
-            .and_then(|h| h.load().ok())
-        });
-
-        debug!("FONT path {:?}, name {:?}", o1, o2);
-
-        o1.or(o2)
-      }).or(default_font).expect("To load FreeMono TTF");
+      .and_then(|font| font.fallback.as_ref())
+      .into_iter()
+      .flatten()
+      .filter_map(|spec| {
+        let face = FontFace::from_spec(spec);
+        if face.is_none() {
+          warn!("Failed to load the fallback font `{}`", spec);
+        }
+        face
+      });
+
+    // the matches are colored unless they are only meant to be underlined
+    let match_underline = config
+      .style
+      .as_ref()
+      .and_then(|style| style.match_underline)
+      .unwrap_or(false);
+    let match_color = config
+      .style
+      .as_ref()
+      .and_then(|style| style.match_color)
+      .or(Some(DEFAULT_MATCH).filter(|_| !match_underline));
+
+    let foreground = config
+      .style
+      .as_ref()
+      .and_then(|style| style.foreground_color)
+      .unwrap_or(DEFAULT_FOREGROUND)
+      .into();
+
+    // the built-in font is the primary font when none is configured and always the last resort
+    let fonts = FontSet::new(primary, fallback.collect(), default_font());
 
     Self {
       context: RendererContext {
-        font,
+        fonts,
+        icons: config
+          .style
+          .as_ref()
+          .filter(|style| style.icons.unwrap_or(false))
+          .map(|style| IconLoader::new(style.icon_theme.as_deref())),
         highlight: config
           .style
           .as_ref()
           .and_then(|style| style.highlight_color)
           .unwrap_or(DEFAULT_HIGHLIGHT)
           .into(),
-        foreground: config
-          .style
-          .as_ref()
-          .and_then(|style| style.foreground_color)
-          .unwrap_or(DEFAULT_FOREGROUND)
-          .into(),
+        foreground,
         background: config
           .style
           .as_ref()
           .and_then(|style| style.background_color)
           .unwrap_or(DEFAULT_BACKGROUND)
           .into(),
+        gradient: config
+          .style
+          .as_ref()
+          .and_then(|style| style.background_gradient.clone())
+          .filter(|gradient| !gradient.stops.is_empty()),
+        image: config
+          .style
+          .as_ref()
+          .and_then(|style| style.background_image.as_ref())
+          .and_then(|image| {
+            let loaded = icon::load_png(&image.path);
+            if loaded.is_none() {
+              warn!(
+                "Failed to load the background image {}",
+                image.path.display()
+              );
+            }
+            loaded.map(|loaded| (loaded, image.mode.unwrap_or_default()))
+          }),
+        selected_background: config
+          .style
+          .as_ref()
+          .and_then(|style| style.selected_background_color)
+          .map(|color| color.into()),
+        selected_padding: config
+          .style
+          .as_ref()
+          .and_then(|style| style.selected_padding)
+          .unwrap_or(ITEM_SPACING / 2.)
+          .max(0.),
+        selected_radius: config
+          .style
+          .as_ref()
+          .and_then(|style| style.selected_corner_radius)
+          .unwrap_or(0.)
+          .max(0.),
+        selected_border: config.style.as_ref().and_then(|style| {
+          let width = style.selected_border_width.unwrap_or(1.);
+          style
+            .selected_border_color
+            .filter(|_| width > 0.)
+            .map(|color| (color.into(), width))
+        }),
+        row_padding: config
+          .style
+          .as_ref()
+          .and_then(|style| style.row_padding)
+          .unwrap_or(0.)
+          .max(0.),
+        separator: config.style.as_ref().and_then(|style| {
+          let width = style.separator_width.unwrap_or(1.);
+          style
+            .separator_color
+            .filter(|_| width > 0.)
+            .map(|color| (color.into(), width))
+        }),
+        matches: MatchStyle {
+          color: match_color.map(|color| color.into()),
+          underline: match_underline,
+        },
+        prompt: config
+          .prompt
+          .clone()
+          .unwrap_or_else(|| String::from(DEFAULT_PROMPT)),
+        placeholder: config.placeholder.clone(),
+        // the foreground dimmed unless configured
+        placeholder_color: config
+          .style
+          .as_ref()
+          .and_then(|style| style.placeholder_color)
+          .map(|color| color.into())
+          .unwrap_or_else(|| dim(foreground)),
+        elements: config
+          .style
+          .as_ref()
+          .and_then(|style| style.elements.clone())
+          .unwrap_or_else(|| DEFAULT_ELEMENTS.to_vec())
+          .into_iter()
+          .filter(|element| {
+            !config.password() || matches!(element, Element::Prompt | Element::Input)
+          })
+          .collect(),
+        input_width: config
+          .style
+          .as_ref()
+          .and_then(|style| style.input_width)
+          .unwrap_or(DEFAULT_INPUT_WIDTH),
+        descriptions: config.descriptions(),
+        password: config.password(),
+        // dimmed like the placeholder unless configured
+        description_color: config
+          .style
+          .as_ref()
+          .and_then(|style| style.description_color)
+          .map(|color| color.into())
+          .unwrap_or_else(|| dim(foreground)),
+        position: config.position(),
+        border: config
+          .style
+          .as_ref()
+          .and_then(|style| style.border_color)
+          .unwrap_or(DEFAULT_BORDER)
+          .into(),
+        border_width: config.border_width(),
+        corner_radius: config
+          .style
+          .as_ref()
+          .and_then(|style| style.corner_radius)
+          .unwrap_or(0.)
+          .max(0.),
+        lines: config.lines(),
         font_spacing: config
           .font
           .as_ref()
@@ -121,123 +395,1532 @@ impl Renderer {
           .unwrap_or(DEFAULT_FONT_SIZE),
       },
       cursor: None,
+      last: None,
+      list_span: (0., 0.),
+      page_start: 0,
+      target: None,
+      background: None,
+      corners: Vec::new(),
+      scratch: None,
     }
   }
-  pub fn render(&mut self, app_context: &AppContext, width: i32, height: i32, canvas: &mut [u8]) {
-    let mut dt = DrawTarget::new(width as i32, height as i32);
+  /// Renders the surface into the canvas and returns the regions drawn again, only the rows
+  /// touched by a moved selection are drawn if nothing else changed since the last frame
+  pub fn render(
+    &mut self,
+    app_context: &AppContext,
+    width: i32,
+    height: i32,
+    canvas: &mut [u8],
+  ) -> Vec<Region> {
+    let state = FrameState {
+      width,
+      height,
+      input: app_context.input.clone(),
+      filtered: app_context.list.filtered_len(),
+      current_index: app_context.current_index,
+    };
+    let redraw = match self.last.replace(state.clone()) {
+      Some(last) if last == state && self.target.is_some() => return Vec::new(),
+      Some(last)
+        if !self.context.password
+          && self.target.is_some()
+          && (last.width, last.height) == (width, height)
+          && (&last.input, last.filtered) == (&state.input, state.filtered) =>
+      {
+        Redraw::Selection(last.current_index)
+      }
+      _ => Redraw::All,
+    };
 
+    let border_width = self.context.border_width;
+    let radius = self
+      .context
+      .corner_radius
+      .min(width as f32 / 2.)
+      .min(height as f32 / 2.);
+
+    let background = match self.background.take() {
+      Some(background) if background.width() == width && background.height() == height => {
+        background
+      }
+      _ => {
+        self.corners = corner_pixels(width, height, radius);
+        self.draw_background(width, height, radius)
+      }
+    };
+
+    let target = match self.target.take() {
+      Some(dt) if dt.width() == width && dt.height() == height => dt,
+      _ => DrawTarget::new(width, height),
+    };
+    // a full frame starts as a copy of the background, the regions of a partial one are drawn
+    // on the scratch target and copied into the last frame one after the other
+    let mut dt = match redraw {
+      Redraw::All => {
+        let mut dt = target;
+        dt.get_data_mut().copy_from_slice(background.get_data());
+        dt
+      }
+      Redraw::Selection(_) => {
+        self.target = Some(target);
+        match self.scratch.take() {
+          Some(scratch) if scratch.width() == width && scratch.height() == height => scratch,
+          _ => DrawTarget::new(width, height),
+        }
+      }
+    };
+    self.background = Some(background);
+
+    // keep the text clear of the border and the rounded corners
+    let area = Area {
+      x: border_width + radius / 2.,
+      y: border_width,
+      width: width as f32 - 2. * border_width - radius,
+      height: height as f32 - 2. * border_width,
+    };
+
+    // everything outside of the rounded rectangle stays transparent
+    if radius > 0. {
+      dt.push_clip(&rounded_rect(0., 0., width as f32, height as f32, radius));
+    } else {
+      dt.push_clip_rect(IntRect::new(
+        IntPoint::new(0, 0),
+        IntPoint::new(width, height),
+      ));
+    }
+
+    let mut damage = Vec::new();
+    if self.context.password {
+      let baseline = area.y + area.height * 3. / 5.;
+      self.draw_elements(&mut dt, app_context, area, baseline);
+    } else if self.context.lines > 0 {
+      self.render_vertical(&mut dt, app_context, area, redraw, &mut damage);
+    } else {
+      self.render_horizontal(&mut dt, app_context, area, redraw, &mut damage);
+    }
+
+    if let Redraw::All = redraw {
+      self.restore_corners(&mut dt);
+    }
+    dt.pop_clip();
+
+    let dt = match redraw {
+      Redraw::All => dt,
+      Redraw::Selection(_) => {
+        self.scratch = Some(dt);
+        self.target.take().expect("Last frame to be kept")
+      }
+    };
+
+    // a new buffer gets the whole frame, only the damaged regions differ from the last one
+    let data = dt.get_data_u8();
+    canvas[..data.len()].copy_from_slice(data);
+
+    self.target = Some(dt);
+
+    match redraw {
+      Redraw::All => vec![Region {
+        x: 0,
+        y: 0,
+        width,
+        height,
+      }],
+      Redraw::Selection(_) => damage,
+    }
+  }
+
+  // restores the background below `area` and clips the drawing to it until `end_redraw`
+  fn redraw_area(&self, dt: &mut DrawTarget, area: Area, damage: &mut Vec<Region>) {
+    let (width, height) = (dt.width(), dt.height());
+    let left = (area.x.floor() as i32).clamp(0, width);
+    let top = (area.y.floor() as i32).clamp(0, height);
+    let right = ((area.x + area.width).ceil() as i32).clamp(left, width);
+    let bottom = ((area.y + area.height).ceil() as i32).clamp(top, height);
+
+    let region = Region {
+      x: left,
+      y: top,
+      width: right - left,
+      height: bottom - top,
+    };
+    if let Some(background) = self.background.as_ref() {
+      copy_region(background.get_data(), dt.get_data_mut(), width, &region);
+    }
+
+    dt.push_clip_rect(IntRect::new(
+      IntPoint::new(left, top),
+      IntPoint::new(right, bottom),
+    ));
+    damage.push(region);
+  }
+
+  // ends the drawing of the last region of `damage` and copies it into the kept frame
+  fn end_redraw(&mut self, dt: &mut DrawTarget, damage: &[Region]) {
+    dt.pop_clip();
+    self.restore_corners(dt);
+    if let (Some(target), Some(region)) = (self.target.as_mut(), damage.last()) {
+      copy_region(dt.get_data(), target.get_data_mut(), dt.width(), region);
+    }
+  }
+
+  // clears the glyphs drawn into the transparent corners
+  fn restore_corners(&self, dt: &mut DrawTarget) {
+    if let Some(background) = self.background.as_ref() {
+      let (source, data) = (background.get_data(), dt.get_data_mut());
+      for &index in &self.corners {
+        data[index] = source[index];
+      }
+    }
+  }
+
+  // the rounded background with the border, drawn once for each size
+  fn draw_background(&self, width: i32, height: i32, radius: f32) -> DrawTarget {
+    let mut dt = DrawTarget::new(width, height);
+    let options = DrawOptions::new();
+    let border_width = self.context.border_width;
+
+    // everything outside of the rounded rectangle stays transparent
+    let shape = rounded_rect(0., 0., width as f32, height as f32, radius);
+    match self.context.gradient.as_ref() {
+      Some(gradient) => dt.fill(
+        &shape,
+        &gradient_source(gradient, width as f32, height as f32),
+        &options,
+      ),
+      None => dt.fill(&shape, &Source::Solid(self.context.background), &options),
+    }
+
+    if let Some((image, mode)) = self.context.image.as_ref() {
+      dt.fill(
+        &shape,
+        &image_source(image, *mode, width as f32, height as f32),
+        &options,
+      );
+    }
+
+    if border_width > 0. {
+      // the stroke is centered on the path, so move it inside by half of its width
+      let border = rounded_rect(
+        border_width / 2.,
+        border_width / 2.,
+        width as f32 - border_width,
+        height as f32 - border_width,
+        (radius - border_width / 2.).max(0.),
+      );
+      dt.stroke(
+        &border,
+        &Source::Solid(self.context.border),
+        &StrokeStyle {
+          width: border_width,
+          ..Default::default()
+        },
+        &options,
+      );
+    }
+
+    dt
+  }
+
+  // dmenu like single line with the input on the left and the entries next to it
+  fn render_horizontal(
+    &mut self,
+    dt: &mut DrawTarget,
+    app_context: &AppContext,
+    area: Area,
+    redraw: Redraw,
+    damage: &mut Vec<Region>,
+  ) {
+    // the description of the selected entry gets a row of its own, away from the screen edge
+    let bounds = area;
+    let (area, tooltip) = if self.context.descriptions {
+      // split at a whole pixel, so the tooltip can be drawn again without touching the bar
+      let split = (area.y + area.height / 2.).round();
+      let row = Area {
+        height: split - area.y,
+        ..area
+      };
+      let below = Area {
+        y: split,
+        height: area.y + area.height - split,
+        ..area
+      };
+      match self.context.position {
+        Position::Bottom => (below, Some(row)),
+        _ => (row, Some(below)),
+      }
+    } else {
+      (area, None)
+    };
+
+    let baseline = area.y + area.height * 3. / 5.;
     let current_index = app_context.current_index;
 
     let options = DrawOptions::new();
     let point_size = self.context.font_size;
-    let highlight_brush = Source::Solid(self.context.highlight);
-    let foreground_brush = Source::Solid(self.context.foreground);
-    let background_brush = Source::Solid(self.context.background);
+    let highlight = self.context.highlight;
+    let foreground = self.context.foreground;
 
-    let filter = &app_context.input;
-    let filter_text = format!("> {}", filter);
+    let spacing = self.context.font_spacing;
+    // the regions drawn again reach up to the edges of the surface, the text may go beyond the
+    // bounds, and a text higher than the rows may reach into the other row, so all is drawn again
+    let surface = Area {
+      x: 0.,
+      y: 0.,
+      width: dt.width() as f32,
+      height: dt.height() as f32,
+    };
+    let outer = |row: Area| {
+      let top = if row.y > bounds.y { row.y } else { 0. };
+      let bottom = if row.y + row.height < bounds.y + bounds.height {
+        row.y + row.height
+      } else {
+        surface.height
+      };
+      Area {
+        y: top,
+        height: bottom - top,
+        ..row
+      }
+    };
+    let overflow = redraw != Redraw::All && tooltip.is_some() && point_size > area.height;
+    if overflow {
+      self.redraw_area(dt, surface, damage);
+    }
 
-    dt.fill_rect(
-      0.,
-      0.,
-      width as f32,
-      height as f32,
-      &background_brush,
-      &options,
-    );
+    // the elements keep their place while only the selection moves
+    let partial = redraw != Redraw::All && !overflow;
+    let (list_start, list_end) = if partial {
+      self.list_span
+    } else {
+      self.draw_elements(dt, app_context, area, baseline)
+    };
+    self.list_span = (list_start, list_end);
 
-    let offset = draw_text(
-      &mut dt,
-      &self.context.font,
-      point_size,
-      filter_text.as_str(),
-      Point::new(0., height as f32 * 3. / 5.),
-      &foreground_brush,
-      &options,
-      self.context.font_spacing,
-    );
+    // the boxes of the entries must not overlap
+    let padding = self.context.selected_padding;
+    let item_spacing = ITEM_SPACING.max(2. * padding);
 
-    self.cursor = Some(offset);
+    // the space of the overflow indicators is always reserved, so the entries do not shift
+    let left_width = text_width(&mut self.context.fonts, point_size, "<", spacing) + item_spacing;
+    let right_width = text_width(&mut self.context.fonts, point_size, ">", spacing);
+    let items_start = list_start + left_width;
+    let items_width = (list_end - right_width - item_spacing - items_start).max(0.);
 
-    let mut start_list = offset.max(200.);
+    let icon_size = area.height as u32;
+    let (page_start, page) = self.turn_page(
+      &app_context.list.filtered,
+      current_index,
+      icon_size,
+      items_width,
+      item_spacing,
+    );
+    self.page_start = page_start;
 
-    // a little space just to be sure
-    start_list += 20.;
+    if partial {
+      let list = Area {
+        x: list_start,
+        width: list_end - list_start,
+        ..area
+      };
+      self.redraw_area(dt, outer(list), damage);
+    }
 
-    if current_index > 0 {
-      start_list = draw_text(
-        &mut dt,
-        &self.context.font,
+    if page_start > 0 {
+      draw_text(
+        dt,
+        &mut self.context.fonts,
         point_size,
         "<",
-        Point::new(start_list, height as f32 * 3. / 5.),
-        &foreground_brush,
+        Point::new(list_start, baseline),
+        foreground,
         &options,
-        self.context.font_spacing,
-      ) + 15.;
+        spacing,
+        None,
+      );
+    }
+
+    if page_start + page.len() < app_context.list.filtered_len() {
+      draw_text(
+        dt,
+        &mut self.context.fonts,
+        point_size,
+        ">",
+        Point::new(list_end - right_width, baseline),
+        foreground,
+        &options,
+        spacing,
+        None,
+      );
     }
 
-    for (index, name) in app_context
-      .list
-      .filtered
+    let mut x = items_start;
+    let mut selected_x = None;
+    for (index, (item, command)) in page
       .iter()
-      .map(|c| &c.name)
-      .skip(current_index)
+      .zip(app_context.list.filtered.iter().skip(page_start))
       .enumerate()
     {
-      start_list = draw_text(
-        &mut dt,
-        &self.context.font,
+      let selected = page_start + index == current_index;
+      if selected {
+        selected_x = Some(x);
+      }
+
+      if index > 0 {
+        self.draw_separator(
+          dt,
+          x - item_spacing / 2.,
+          area.y + self.context.row_padding,
+          area.height - 2. * self.context.row_padding,
+          Orientation::Vertical,
+        );
+      }
+
+      if selected {
+        self.draw_selection(
+          dt,
+          x - padding,
+          area.y,
+          item.width + 2. * padding,
+          area.height,
+        );
+      }
+
+      if item.icon {
+        if let Some(icons) = self.context.icons.as_mut() {
+          draw_icon(dt, icons, command.icon_name(), x, area.y, icon_size);
+        }
+      }
+
+      draw_text(
+        dt,
+        &mut self.context.fonts,
         point_size,
-        name,
-        Point::new(start_list, height as f32 * 3. / 5.),
-        if index == 0 {
-          &highlight_brush
-        } else {
-          &foreground_brush
+        &item.label,
+        Point::new(x + item.label_offset, baseline),
+        if selected { highlight } else { foreground },
+        &options,
+        spacing,
+        Some(&Matches {
+          offsets: visible_matches(&command.matches, &command.name, &item.label),
+          style: self.context.matches,
+        }),
+      );
+
+      x += item.width + item_spacing;
+    }
+
+    if partial {
+      self.end_redraw(dt, damage);
+    }
+
+    if let Some(tooltip) = tooltip {
+      // the box of the description may reach into the border
+      let band = Area {
+        x: 0.,
+        width: surface.width,
+        ..tooltip
+      };
+      if partial {
+        self.redraw_area(dt, outer(band), damage);
+      }
+      self.draw_tooltip(dt, app_context, tooltip, selected_x);
+      if partial {
+        self.end_redraw(dt, damage);
+      }
+    }
+
+    if overflow {
+      self.end_redraw(dt, damage);
+    }
+  }
+
+  // the description of the selected entry in its own row
+  fn draw_tooltip(
+    &mut self,
+    dt: &mut DrawTarget,
+    app_context: &AppContext,
+    tooltip: Area,
+    selected_x: Option<f32>,
+  ) {
+    let (Some(x), Some(command)) = (selected_x, app_context.target()) else {
+      return;
+    };
+    let Some(description) = command.description.as_ref() else {
+      return;
+    };
+    let (point_size, spacing) = (self.context.font_size, self.context.font_spacing);
+    let padding = self.context.selected_padding;
+
+    // below the selected entry, shifted to the left where it would not fit otherwise
+    let label = ellipsize(
+      &mut self.context.fonts,
+      point_size,
+      description,
+      tooltip.width,
+      spacing,
+    );
+    let width = text_width(&mut self.context.fonts, point_size, &label, spacing);
+    let x = x.min(tooltip.x + tooltip.width - width).max(tooltip.x);
+
+    self.draw_selection(
+      dt,
+      x - padding,
+      tooltip.y,
+      width + 2. * padding,
+      tooltip.height,
+    );
+
+    self.draw_description(
+      dt,
+      command,
+      description,
+      Point::new(x, tooltip.y + tooltip.height * 3. / 5.),
+      tooltip.width,
+    );
+  }
+
+  // the box of the selected entry within the row from `y` to `y + height`
+  fn draw_selection(&self, dt: &mut DrawTarget, x: f32, y: f32, width: f32, height: f32) {
+    let padding = self.context.row_padding;
+    let (y, height) = (y + padding, (height - 2. * padding).max(0.));
+    let radius = self
+      .context
+      .selected_radius
+      .min(width / 2.)
+      .min(height / 2.);
+    let options = DrawOptions::new();
+
+    if let Some(background) = self.context.selected_background {
+      dt.fill(
+        &rounded_rect(x, y, width, height, radius),
+        &Source::Solid(background),
+        &options,
+      );
+    }
+
+    if let Some((color, border_width)) = self.context.selected_border {
+      // inside of the box like the border of the surface
+      let border = rounded_rect(
+        x + border_width / 2.,
+        y + border_width / 2.,
+        width - border_width,
+        height - border_width,
+        (radius - border_width / 2.).max(0.),
+      );
+      dt.stroke(
+        &border,
+        &Source::Solid(color),
+        &StrokeStyle {
+          width: border_width,
+          ..Default::default()
         },
         &options,
+      );
+    }
+  }
+
+  // a horizontal or vertical line of `length` from the given point, its width centered on it
+  fn draw_separator(
+    &self,
+    dt: &mut DrawTarget,
+    x: f32,
+    y: f32,
+    length: f32,
+    orientation: Orientation,
+  ) {
+    let Some((color, line_width)) = self.context.separator else {
+      return;
+    };
+
+    let (x, y, width, height) = match orientation {
+      Orientation::Horizontal => (x, y - line_width / 2., length, line_width),
+      Orientation::Vertical => (x - line_width / 2., y, line_width, length),
+    };
+    dt.fill_rect(
+      x,
+      y,
+      width,
+      height,
+      &Source::Solid(color),
+      &DrawOptions::new(),
+    );
+  }
+
+  // draws the elements around the list in the order configured and returns the space left for it
+  fn draw_elements(
+    &mut self,
+    dt: &mut DrawTarget,
+    app_context: &AppContext,
+    area: Area,
+    baseline: f32,
+  ) -> (f32, f32) {
+    let elements = self.context.elements.clone();
+    let split = elements
+      .iter()
+      .position(|element| *element == Element::List)
+      .unwrap_or(elements.len());
+    // the prompt brings its own spacing to the element next to it
+    let spacing = |element: Option<&Element>| match element {
+      Some(Element::Prompt) => 0.,
+      _ => ELEMENT_SPACING,
+    };
+
+    let mut start = area.x;
+    for element in &elements[..split] {
+      let width = self.draw_element(dt, app_context, *element, start, baseline, area.width);
+      start += width + spacing(Some(element));
+    }
+
+    let mut end = area.x + area.width;
+    let mut right = elements[split..].iter().skip(1).rev().peekable();
+    while let Some(element) = right.next() {
+      let width = self.element_width(app_context, *element, area.width);
+      end -= width;
+      self.draw_element(dt, app_context, *element, end, baseline, area.width);
+      end -= spacing(right.peek().copied());
+    }
+
+    (start, end.max(start))
+  }
+
+  // the text of an element and its color
+  fn element_text(&self, app_context: &AppContext, element: Element) -> (String, SolidSource) {
+    let foreground = self.context.foreground;
+    match element {
+      Element::Prompt => (self.context.prompt.clone(), foreground),
+      Element::Input => match self.context.placeholder.as_ref() {
+        Some(placeholder) if app_context.input.is_empty() => {
+          (placeholder.clone(), self.context.placeholder_color)
+        }
+        _ if self.context.password => (
+          PASSWORD_BULLET.repeat(app_context.input.chars().count()),
+          foreground,
+        ),
+        _ => (app_context.input.clone(), foreground),
+      },
+      Element::Counter => (
+        format!(
+          "{}/{}",
+          app_context.list.filtered_len(),
+          app_context.list.total_len()
+        ),
+        foreground,
+      ),
+      Element::List => (String::new(), foreground),
+    }
+  }
+
+  // the space taken by an element, the input and the counter do not change their width while typing
+  fn element_width(&mut self, app_context: &AppContext, element: Element, available: f32) -> f32 {
+    let (text, _) = self.element_text(app_context, element);
+    let (point_size, spacing) = (self.context.font_size, self.context.font_spacing);
+    let width = text_width(&mut self.context.fonts, point_size, &text, spacing);
+
+    match element {
+      Element::Input => width.max(match self.context.input_width {
+        Width::Pixels(pixels) => pixels as f32,
+        Width::Percent(percent) => available * percent / 100.,
+      }),
+      Element::Counter => {
+        let total = app_context.list.total_len();
+        let widest = format!("{}/{}", total, total);
+        width.max(text_width(
+          &mut self.context.fonts,
+          point_size,
+          &widest,
+          spacing,
+        ))
+      }
+      _ => width,
+    }
+  }
+
+  fn draw_element(
+    &mut self,
+    dt: &mut DrawTarget,
+    app_context: &AppContext,
+    element: Element,
+    x: f32,
+    baseline: f32,
+    available: f32,
+  ) -> f32 {
+    let (text, color) = self.element_text(app_context, element);
+    draw_text(
+      dt,
+      &mut self.context.fonts,
+      self.context.font_size,
+      &text,
+      Point::new(x, baseline),
+      color,
+      &DrawOptions::new(),
+      self.context.font_spacing,
+      None,
+    );
+
+    if element == Element::Input {
+      self.cursor = Some(if app_context.input.is_empty() {
+        x
+      } else {
+        x + text_width(
+          &mut self.context.fonts,
+          self.context.font_size,
+          &text,
+          self.context.font_spacing,
+        )
+      });
+    }
+
+    self.element_width(app_context, element, available)
+  }
+
+  // the label of an entry next to its icon, shortened to fit into `max_width`
+  fn layout_item(&mut self, command: &Command, icon_size: u32, max_width: f32) -> Item {
+    let icon = self
+      .context
+      .icons
+      .as_mut()
+      .and_then(|icons| icons.get(command.icon_name(), icon_size))
+      .is_some();
+    let label_offset = if icon {
+      icon_size as f32 + ICON_SPACING
+    } else {
+      0.
+    };
+
+    let label = ellipsize(
+      &mut self.context.fonts,
+      self.context.font_size,
+      &command.name,
+      max_width - label_offset,
+      self.context.font_spacing,
+    );
+    let width = label_offset
+      + text_width(
+        &mut self.context.fonts,
+        self.context.font_size,
+        &label,
         self.context.font_spacing,
-      ) + 15.;
+      );
 
-      // break if we are outside
-      if start_list > width as f32 {
+    Item {
+      label,
+      icon,
+      label_offset,
+      width,
+    }
+  }
+
+  // the entries from `start` on that fit into `width`, at least one
+  fn layout_page(
+    &mut self,
+    list: &[Command],
+    start: usize,
+    icon_size: u32,
+    width: f32,
+    spacing: f32,
+  ) -> Vec<Item> {
+    let mut page = Vec::new();
+    let mut used = 0.;
+    for command in list.iter().skip(start) {
+      let item = self.layout_item(command, icon_size, width);
+      if !page.is_empty() && used + item.width > width {
         break;
       }
+      used += item.width + spacing;
+      page.push(item);
     }
+    page
+  }
 
-    for (src, dst) in dt
-      .get_data_u8()
-      .chunks_exact(4)
-      .zip(canvas.chunks_exact_mut(4))
-    {
-      dst[0] = src[0];
-      dst[1] = src[1];
-      dst[2] = src[2];
-      dst[3] = src[3];
+  // the first entry and the entries of the bar's page showing the selection. The page goes on
+  // from the first entry of the last frame, it is turned once the selection reaches one of its
+  // ends, so an entry stays visible next to it on both sides
+  fn turn_page(
+    &mut self,
+    list: &[Command],
+    current_index: usize,
+    icon_size: u32,
+    width: f32,
+    spacing: f32,
+  ) -> (usize, Vec<Item>) {
+    // nothing matches the input
+    if list.is_empty() {
+      return (0, Vec::new());
+    }
+
+    let selection = current_index.min(list.len() - 1);
+    let mut page_start = self.page_start.min(selection);
+    let mut page = self.layout_page(list, page_start, icon_size, width, spacing);
+    let turned = if selection + 1 >= page_start + page.len() && selection + 1 < list.len() {
+      selection.saturating_sub(1)
+    } else if selection == page_start && page_start > 0 {
+      let end = (selection + 1).min(list.len() - 1);
+      self
+        .page_ending_at(list, end, icon_size, width, spacing)
+        .min(selection)
+    } else {
+      page_start
+    };
+    if turned != page_start {
+      page_start = turned;
+      page = self.layout_page(list, page_start, icon_size, width, spacing);
+    }
+    // the entries before the selection were too wide to keep it on the page
+    if selection >= page_start + page.len() {
+      page_start = self.page_ending_at(list, selection, icon_size, width, spacing);
+      page = self.layout_page(list, page_start, icon_size, width, spacing);
+    }
+    (page_start, page)
+  }
+
+  // the first entry of the page that ends with the one at `end`
+  fn page_ending_at(
+    &mut self,
+    list: &[Command],
+    end: usize,
+    icon_size: u32,
+    width: f32,
+    spacing: f32,
+  ) -> usize {
+    let mut start = end;
+    let mut used = self.layout_item(&list[end], icon_size, width).width;
+    while start > 0 {
+      let item = self.layout_item(&list[start - 1], icon_size, width);
+      if used + spacing + item.width > width {
+        break;
+      }
+      used += spacing + item.width;
+      start -= 1;
+    }
+    start
+  }
+
+  // the first entry of the vertical list's page showing the selection. The page goes on from the
+  // first entry of the last frame and scrolls once the selection reaches its first or last row,
+  // so an entry stays visible next to it on both sides if there are at least three rows
+  fn scroll(&self, len: usize, current_index: usize, lines: usize) -> usize {
+    if len == 0 {
+      return 0;
+    }
+    let selection = current_index.min(len - 1);
+    let margin = usize::from(lines >= 3);
+    let mut start = self.page_start;
+    if selection + margin >= start + lines {
+      start = (selection + margin + 1).min(len).saturating_sub(lines);
+    }
+    if selection < start + margin {
+      start = selection.saturating_sub(margin);
+    }
+    // a page is not left half empty while there are entries above it
+    start.min(len.saturating_sub(lines))
+  }
+
+  // the input in the first row and one entry per row below
+  fn render_vertical(
+    &mut self,
+    dt: &mut DrawTarget,
+    app_context: &AppContext,
+    bounds: Area,
+    redraw: Redraw,
+    damage: &mut Vec<Region>,
+  ) {
+    // the box of the selected row spans the whole width, the content stays clear of its edges
+    let padding = self.context.selected_padding.min(bounds.width / 2.);
+    let area = Area {
+      x: bounds.x + padding,
+      width: bounds.width - 2. * padding,
+      ..bounds
+    };
+    let current_index = app_context.current_index;
+    let lines = self.context.lines as usize;
+    let row_height = area.height / (lines + 1) as f32;
+    // the rows start at whole pixels, so a row can be drawn again without touching its neighbours
+    let row_top = |row: usize| (area.y + row_height * row as f32).round();
+    let baseline = |row: usize| row_top(row) + row_height * 3. / 5.;
+
+    let options = DrawOptions::new();
+    let point_size = self.context.font_size;
+    let highlight = self.context.highlight;
+    let foreground = self.context.foreground;
+
+    // the list scrolls once the selection reaches its first or last row and the indicators mark
+    // the entries above and below
+    let last_page_start = self.page_start;
+    let page_start = self.scroll(app_context.list.filtered_len(), current_index, lines);
+    self.page_start = page_start;
+
+    // a selection moving within the page only changes the rows it leaves and enters
+    let rows: Option<Vec<usize>> = match redraw {
+      Redraw::All => None,
+      Redraw::Selection(previous) if last_page_start == page_start => Some(vec![
+        previous - page_start + 1,
+        current_index - page_start + 1,
+      ]),
+      Redraw::Selection(_) => Some((1..=lines).collect()),
+    };
+    // the text may reach into the rows next to it, so these are drawn again as well and a row
+    // drawn again gets the content of its neighbours too
+    let reach = match rows {
+      Some(_) => (point_size / row_height).ceil() as usize,
+      None => 0,
+    };
+    let rows = rows.map(|rows| {
+      rows
+        .into_iter()
+        .flat_map(|row| row.saturating_sub(reach)..=(row + reach).min(lines))
+        .collect::<Vec<_>>()
+    });
+    let dirty = |row: usize| match rows.as_ref() {
+      Some(rows) => rows.contains(&row),
+      None => true,
+    };
+
+    // the labels stay aligned, whether an entry has an icon or not
+    let icon_size = row_height as u32;
+    let label_x = match self.context.icons {
+      Some(_) => area.x + icon_size as f32 + ICON_SPACING,
+      None => area.x,
+    };
+
+    let indicator = point_size / 2.;
+    let label_width = area.x + area.width - label_x - indicator - ITEM_SPACING;
+    let indicator_x = area.x + area.width - indicator;
+    let last_row = lines.min(app_context.list.filtered_len().saturating_sub(page_start));
+
+    // the descriptions start in a second column after the widest name of the page
+    let name_width = if self.context.descriptions {
+      let widest = app_context
+        .list
+        .filtered
+        .iter()
+        .skip(page_start)
+        .take(lines)
+        .map(|command| {
+          text_width(
+            &mut self.context.fonts,
+            point_size,
+            &command.name,
+            self.context.font_spacing,
+          )
+        })
+        .fold(0., f32::max);
+      widest.min(label_width / 2.)
+    } else {
+      label_width
+    };
+    let description_x = label_x + name_width + ELEMENT_SPACING;
+    let description_width = label_width - name_width - ELEMENT_SPACING;
+
+    for dirty_row in 0..=lines {
+      if !dirty(dirty_row) {
+        continue;
+      }
+      let partial = rows.is_some();
+      if partial {
+        // the rows span the whole width and the outer ones reach up to the edges of the surface,
+        // the boxes and the text may go beyond the bounds
+        let top = match dirty_row {
+          0 => 0.,
+          _ => row_top(dirty_row),
+        };
+        let bottom = match dirty_row {
+          _ if dirty_row == lines => dt.height() as f32,
+          _ => row_top(dirty_row + 1),
+        };
+        let area = Area {
+          x: 0.,
+          y: top,
+          width: dt.width() as f32,
+          height: bottom - top,
+        };
+        self.redraw_area(dt, area, damage);
+      }
+
+      for row in dirty_row.saturating_sub(reach)..=(dirty_row + reach).min(lines) {
+        // the first row is laid out like the bar, just without the list
+        if row == 0 {
+          self.draw_elements(dt, app_context, area, baseline(0));
+          continue;
+        }
+        let index = page_start + row - 1;
+        if let Some(command) = app_context
+          .list
+          .filtered
+          .get(index)
+          .filter(|_| row <= last_row)
+        {
+          let selected = index == current_index;
+
+          // the separators run along the top of the rows including the one below the input
+          self.draw_separator(
+            dt,
+            bounds.x,
+            row_top(row),
+            bounds.width,
+            Orientation::Horizontal,
+          );
+
+          if selected {
+            let height = row_top(row + 1) - row_top(row);
+            self.draw_selection(dt, bounds.x, row_top(row), bounds.width, height);
+          }
+
+          if let Some(icons) = self.context.icons.as_mut() {
+            draw_icon(
+              dt,
+              icons,
+              command.icon_name(),
+              area.x,
+              row_top(row),
+              icon_size,
+            );
+          }
+
+          let label = ellipsize(
+            &mut self.context.fonts,
+            point_size,
+            &command.name,
+            name_width,
+            self.context.font_spacing,
+          );
+
+          draw_text(
+            dt,
+            &mut self.context.fonts,
+            point_size,
+            &label,
+            Point::new(label_x, baseline(row)),
+            if selected { highlight } else { foreground },
+            &options,
+            self.context.font_spacing,
+            Some(&Matches {
+              offsets: visible_matches(&command.matches, &command.name, &label),
+              style: self.context.matches,
+            }),
+          );
+
+          if let Some(description) = command
+            .description
+            .as_ref()
+            .filter(|_| self.context.descriptions)
+          {
+            self.draw_description(
+              dt,
+              command,
+              description,
+              Point::new(description_x, baseline(row)),
+              description_width,
+            );
+          }
+        }
+
+        if row == 1 && page_start > 0 {
+          draw_indicator(
+            dt,
+            indicator_x,
+            row_top(1),
+            row_height,
+            indicator,
+            true,
+            foreground,
+          );
+        }
+        if row == last_row && page_start + lines < app_context.list.filtered_len() {
+          draw_indicator(
+            dt,
+            indicator_x,
+            row_top(last_row),
+            row_height,
+            indicator,
+            false,
+            foreground,
+          );
+        }
+      }
+
+      if partial {
+        self.end_redraw(dt, damage);
+      }
+    }
+  }
+
+  // the description of an entry shortened to `max_width`
+  fn draw_description(
+    &mut self,
+    dt: &mut DrawTarget,
+    command: &Command,
+    description: &str,
+    start: Point,
+    max_width: f32,
+  ) {
+    let label = ellipsize(
+      &mut self.context.fonts,
+      self.context.font_size,
+      description,
+      max_width,
+      self.context.font_spacing,
+    );
+
+    draw_text(
+      dt,
+      &mut self.context.fonts,
+      self.context.font_size,
+      &label,
+      start,
+      self.context.description_color,
+      &DrawOptions::new(),
+      self.context.font_spacing,
+      Some(&Matches {
+        offsets: visible_matches(&command.description_matches, description, &label),
+        style: self.context.matches,
+      }),
+    );
+  }
+}
+
+// the gradient spanning the whole surface
+fn gradient_source(gradient: &Gradient, width: f32, height: f32) -> Source<'static> {
+  let last = gradient.stops.len().saturating_sub(1).max(1) as f32;
+  let stops = gradient
+    .stops
+    .iter()
+    .enumerate()
+    .map(|(index, stop)| {
+      let (color, position) = match *stop {
+        GradientStop::Color(color) => (color, index as f32 / last),
+        GradientStop::At { color, position } => (color, position.clamp(0., 1.)),
+      };
+      raqote::GradientStop {
+        position,
+        color: raqote::Color::new(color.a, color.r, color.g, color.b),
+      }
+    })
+    .collect();
+  let stops = raqote::Gradient { stops };
+  let center = Point::new(width / 2., height / 2.);
+
+  match gradient.kind.unwrap_or_default() {
+    GradientKind::Linear => {
+      // the gradient line runs through the center and touches the corners like in CSS
+      let angle = gradient.angle.unwrap_or(180.).to_radians();
+      let (sin, cos) = angle.sin_cos();
+      let length = (width * sin).abs() + (height * cos).abs();
+      let (dx, dy) = (sin * length / 2., -cos * length / 2.);
+      Source::new_linear_gradient(
+        stops,
+        Point::new(center.x - dx, center.y - dy),
+        Point::new(center.x + dx, center.y + dy),
+        Spread::Pad,
+      )
+    }
+    // out to the corners
+    GradientKind::Radial => {
+      Source::new_radial_gradient(stops, center, center.x.hypot(center.y), Spread::Pad)
     }
   }
 }
 
+// the image scaled to cover the surface or repeated from the top left corner
+fn image_source(image: &Icon, mode: ImageMode, width: f32, height: f32) -> Source<'_> {
+  let (image_width, image_height) = (image.width as f32, image.height as f32);
+  // the transform maps the surface onto the image
+  let transform = match mode {
+    ImageMode::Scale => {
+      let scale = (width / image_width).max(height / image_height);
+      Transform::translation(
+        -(width - image_width * scale) / 2.,
+        -(height - image_height * scale) / 2.,
+      )
+      .then_scale(1. / scale, 1. / scale)
+    }
+    ImageMode::Tile => Transform::identity(),
+  };
+
+  Source::Image(
+    Image {
+      width: image.width,
+      height: image.height,
+      data: &image.data,
+    },
+    match mode {
+      ImageMode::Scale => ExtendMode::Pad,
+      ImageMode::Tile => ExtendMode::Repeat,
+    },
+    FilterMode::Bilinear,
+    transform,
+  )
+}
+
+// a small triangle at the right of a row pointing up or down
+fn draw_indicator(
+  dt: &mut DrawTarget,
+  x: f32,
+  top: f32,
+  height: f32,
+  size: f32,
+  up: bool,
+  color: SolidSource,
+) {
+  let middle = top + height / 2.;
+  let (tip, base) = if up {
+    (middle - size / 2., middle + size / 2.)
+  } else {
+    (middle + size / 2., middle - size / 2.)
+  };
+
+  let mut pb = PathBuilder::new();
+  pb.move_to(x, base);
+  pb.line_to(x + size, base);
+  pb.line_to(x + size / 2., tip);
+  pb.close();
+  dt.fill(&pb.finish(), &Source::Solid(color), &DrawOptions::new());
+}
+
+// shortens the text to the longest prefix that fits into `max_width` together with an ellipsis
+fn ellipsize(
+  fonts: &mut FontSet,
+  point_size: f32,
+  text: &str,
+  max_width: f32,
+  space_factor: f32,
+) -> String {
+  if text_width(fonts, point_size, text, space_factor) <= max_width {
+    return String::from(text);
+  }
+
+  let mut cuts: Vec<usize> = text.char_indices().map(|(index, _)| index).collect();
+  cuts.push(text.len());
+
+  let fits = |fonts: &mut FontSet, chars: usize| {
+    let candidate = format!("{}{}", &text[..cuts[chars]], ELLIPSIS);
+    fonts.layout(&candidate, point_size, space_factor).width <= max_width
+  };
+
+  // the number of characters to keep
+  let (mut low, mut high) = (0, cuts.len() - 1);
+  while low < high {
+    let middle = (low + high).div_ceil(2);
+    if fits(fonts, middle) {
+      low = middle;
+    } else {
+      high = middle - 1;
+    }
+  }
+
+  format!("{}{}", text[..cuts[low]].trim_end(), ELLIPSIS)
+}
+
+// the color at half of its opacity
+fn dim(color: SolidSource) -> SolidSource {
+  SolidSource {
+    r: color.r / 2,
+    g: color.g / 2,
+    b: color.b / 2,
+    a: color.a / 2,
+  }
+}
+
+// the matches of a text that are not cut off by the ellipsis of its label
+fn visible_matches<'a>(matches: &'a [usize], text: &str, label: &str) -> &'a [usize] {
+  let visible = match label.strip_suffix(ELLIPSIS) {
+    Some(prefix) if label != text => prefix.len(),
+    _ => label.len(),
+  };
+  &matches[..matches.partition_point(|offset| *offset < visible)]
+}
+
+fn rounded_rect(x: f32, y: f32, width: f32, height: f32, radius: f32) -> DrawPath {
+  let mut pb = PathBuilder::new();
+  if radius <= 0. {
+    pb.rect(x, y, width, height);
+  } else {
+    let quarter = std::f32::consts::FRAC_PI_2;
+    pb.move_to(x + radius, y);
+    pb.line_to(x + width - radius, y);
+    pb.arc(x + width - radius, y + radius, radius, -quarter, quarter);
+    pb.line_to(x + width, y + height - radius);
+    pb.arc(x + width - radius, y + height - radius, radius, 0., quarter);
+    pb.line_to(x + radius, y + height);
+    pb.arc(x + radius, y + height - radius, radius, quarter, quarter);
+    pb.line_to(x, y + radius);
+    pb.arc(x + radius, y + radius, radius, 2. * quarter, quarter);
+    pb.close();
+  }
+  pb.finish()
+}
+
+// draws the icon scaled into a square of `size` pixels, returns false if there is none
+fn draw_icon(
+  dt: &mut DrawTarget,
+  icons: &mut IconLoader,
+  name: &str,
+  x: f32,
+  y: f32,
+  size: u32,
+) -> bool {
+  let Some(icon) = icons.get(name, size) else {
+    return false;
+  };
+
+  // keep the aspect ratio and center the icon within the square
+  let size = size as f32;
+  let scale = size / icon.width.max(icon.height) as f32;
+  let (width, height) = (icon.width as f32 * scale, icon.height as f32 * scale);
+
+  dt.draw_image_with_size_at(
+    width,
+    height,
+    x + (size - width) / 2.,
+    y + (size - height) / 2.,
+    &Image {
+      width: icon.width,
+      height: icon.height,
+      data: &icon.data,
+    },
+    &DrawOptions::new(),
+  );
+  true
+}
+
+// copies the pixels of the region between buffers of the same size
+fn copy_region(source: &[u32], target: &mut [u32], width: i32, region: &Region) {
+  for y in region.y..region.y + region.height {
+    let start = (y * width + region.x) as usize;
+    let range = start..start + region.width as usize;
+    target[range.clone()].copy_from_slice(&source[range]);
+  }
+}
+
+// the pixels left out by the rounded rectangle of the background
+fn corner_pixels(width: i32, height: i32, radius: f32) -> Vec<usize> {
+  if radius <= 0. {
+    return Vec::new();
+  }
+  let mut shape = DrawTarget::new(width, height);
+  shape.fill(
+    &rounded_rect(0., 0., width as f32, height as f32, radius),
+    &Source::Solid(SolidSource::from_unpremultiplied_argb(0xFF, 0, 0, 0)),
+    &DrawOptions::new(),
+  );
+  shape
+    .get_data()
+    .iter()
+    .enumerate()
+    .filter(|(_, pixel)| **pixel >> 24 == 0)
+    .map(|(index, _)| index)
+    .collect()
+}
+
+// composes the color with the coverage of the mask over the target, pixel by pixel as raqote's
+// `mask` places the mask at the wrong offset. The clip of the target is not applied, the glyphs
+// are only kept within the target and the renderer clears its transparent corners afterwards
+fn blit_mask(dt: &mut DrawTarget, color: SolidSource, x: i32, y: i32, mask: &Mask) {
+  let (width, height) = (dt.width(), dt.height());
+  let columns = (-x).max(0)..mask.width.min(width - x);
+  if columns.is_empty() {
+    return;
+  }
+
+  let data = dt.get_data_mut();
+  for row in (-y).max(0)..mask.height.min(height - y) {
+    let source = &mask.data[(row * mask.width) as usize..][..mask.width as usize];
+
+    for column in columns.clone() {
+      let coverage = source[column as usize] as u32;
+      if coverage == 0 {
+        continue;
+      }
+
+      let scale = |value: u8| (value as u32 * coverage + 127) / 255;
+      let alpha = scale(color.a);
+      // the columns are clipped to the target, so the index is never negative
+      let pixel = &mut data[((y + row) * width + x + column) as usize];
+      let blend =
+        |value: u32, shift: u32| value + (((*pixel >> shift) & 0xFF) * (255 - alpha) + 127) / 255;
+      *pixel = blend(alpha, 24) << 24
+        | blend(scale(color.r), 16) << 16
+        | blend(scale(color.g), 8) << 8
+        | blend(scale(color.b), 0);
+    }
+  }
+}
+
+fn text_width(fonts: &mut FontSet, point_size: f32, text: &str, space_factor: f32) -> f32 {
+  fonts.layout(text, point_size, space_factor).width
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_text(
   dt: &mut DrawTarget,
-  font: &Font,
+  fonts: &mut FontSet,
   point_size: f32,
   text: &str,
   start: Point,
-  src: &Source,
+  color: SolidSource,
   options: &DrawOptions,
   space_factor: f32,
+  matches: Option<&Matches>,
 ) -> f32 {
-  let mut start = pathfinder_geometry::vector::vec2f(start.x, start.y);
-  let mut ids = Vec::new();
-  let mut positions = Vec::new();
-  for c in text.chars() {
-    let id = font.glyph_for_char(c).unwrap();
-    ids.push(id);
-    positions.push(Point::new(start.x(), start.y()));
-    start += font.advance(id).unwrap() * point_size / 24. / 96. * space_factor;
-  }
-  dt.draw_glyphs(font, point_size, &ids, &positions, src, options);
-  start.x()
+  let shaped = fonts.layout(text, point_size, space_factor);
+  let mut underlines: Vec<(f32, f32)> = Vec::new();
+
+  for (index, glyph) in shaped.glyphs.iter().enumerate() {
+    let x = start.x + glyph.x;
+    let y = (start.y + glyph.y).round() as i32;
+
+    let color = match matches {
+      Some(matches) if matches.offsets.binary_search(&glyph.cluster).is_ok() => {
+        if matches.style.underline {
+          // from the glyph to the next cluster, marks share the line of their base
+          let end = shaped.glyphs[index + 1..]
+            .iter()
+            .find(|next| next.cluster != glyph.cluster)
+            .map_or(shaped.width, |next| next.x);
+          match underlines.last_mut() {
+            Some((_, last)) if *last >= glyph.x => *last = last.max(end),
+            _ => underlines.push((glyph.x, end)),
+          }
+        }
+        matches.style.color.unwrap_or(color)
+      }
+      _ => color,
+    };
+
+    match glyph.face {
+      Some(face) => {
+        // blit the cached coverage at the nearest subpixel offset
+        let subpixels = (x * SUBPIXELS as f32).round() as i32;
+        let (pixel, subpixel) = (
+          subpixels.div_euclid(SUBPIXELS as i32),
+          subpixels.rem_euclid(SUBPIXELS as i32) as u32,
+        );
+        if let Some(rasterized) = fonts.rasterize(face, glyph.id, point_size, subpixel) {
+          blit_mask(
+            dt,
+            color,
+            pixel + rasterized.left,
+            y + rasterized.top,
+            &rasterized.mask,
+          );
+        }
+      }
+      None => {
+        // no font covers this character, so draw a box
+        let mut pb = PathBuilder::new();
+        pb.rect(
+          x + point_size * 0.05,
+          start.y - point_size * 0.7,
+          point_size * 0.5,
+          point_size * 0.7,
+        );
+        dt.stroke(
+          &pb.finish(),
+          &Source::Solid(color),
+          &StrokeStyle {
+            width: 1.,
+            ..Default::default()
+          },
+          options,
+        );
+      }
+    }
+  }
+
+  if let Some(matches) = matches {
+    let thickness = (point_size / 16.).round().max(1.);
+    let color = matches.style.color.unwrap_or(color);
+    for (from, to) in underlines {
+      dt.fill_rect(
+        start.x + from,
+        (start.y + point_size * 0.15).round(),
+        to - from,
+        thickness,
+        &Source::Solid(color),
+        options,
+      );
+    }
+  }
+
+  start.x + shaped.width
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::{
+    action::Action,
+    command::CommandList,
+    config::{AppConfig, Style},
+  };
+
+  fn context(lines: Option<u32>, entries: usize) -> AppContext {
+    let config = AppConfig::from(StaticConfig {
+      style: Some(Style {
+        lines,
+        ..Default::default()
+      }),
+      ..Default::default()
+    });
+    let entries = (0..entries)
+      .map(|index| PathBuf::from(format!("/bin/entry{:02}", index)))
+      .collect();
+    let list = CommandList::from_paths(&config, entries);
+    AppContext::with_list(config, list)
+  }
+
+  fn render(renderer: &mut Renderer, context: &AppContext, width: i32, height: i32) {
+    let mut canvas = vec![0u8; (width * height * 4) as usize];
+    renderer.render(context, width, height, &mut canvas);
+  }
+
+  #[test]
+  fn renders_no_matches() {
+    for lines in [None, Some(3)] {
+      let mut context = context(lines, 5);
+      let mut renderer = Renderer::new(&context.config.static_config);
+      let height = context.config.static_config.height() as i32;
+      render(&mut renderer, &context, 400, height);
+
+      context.apply(Action::InsertText(String::from("zzz")));
+      assert_eq!(context.list.filtered_len(), 0);
+      render(&mut renderer, &context, 400, height);
+      assert_eq!(renderer.page_start, 0);
+    }
+  }
+
+  #[test]
+  fn vertical_list_keeps_a_neighbour_of_the_selection() {
+    let (lines, entries) = (4, 10);
+    let mut context = context(Some(lines as u32), entries);
+    let mut renderer = Renderer::new(&context.config.static_config);
+    let height = context.config.static_config.height() as i32;
+
+    for step in 0..2 * (entries - 1) {
+      if step < entries - 1 {
+        context.apply(Action::MoveNext);
+      } else {
+        context.apply(Action::MovePrevious);
+      }
+      render(&mut renderer, &context, 400, height);
+
+      let (selection, start) = (context.current_index, renderer.page_start);
+      assert!(start <= selection && selection < start + lines);
+      // an entry above and below stays visible unless the selection is at an end of the list
+      assert!(
+        selection == 0 || start < selection,
+        "{} at {}",
+        selection,
+        start
+      );
+      assert!(
+        selection + 1 == entries || selection + 1 < start + lines,
+        "{} at {}",
+        selection,
+        start
+      );
+    }
+  }
 }
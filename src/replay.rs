@@ -0,0 +1,300 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+
+use rmenu::{Action, AppConfig, AppContext, Command, CommandList, Key, Outcome};
+
+use crate::render;
+
+// what the menu would have done after the replayed keys
+enum Decision {
+  // the keys ran out before the menu was closed
+  Pending,
+  Launch,
+  // the input of a password prompt
+  Submit,
+  NoMatch,
+  Cancel,
+}
+
+impl Decision {
+  fn name(&self) -> &'static str {
+    match self {
+      Decision::Pending => "pending",
+      Decision::Launch => "launch",
+      Decision::Submit => "submit",
+      Decision::NoMatch => "no_match",
+      Decision::Cancel => "cancel",
+    }
+  }
+  // the exit code of rmenu, unknown for a launch as nothing gets spawned
+  fn exit_code(&self, password: bool) -> Option<i32> {
+    match self {
+      Decision::Pending | Decision::Launch => None,
+      Decision::Submit => Some(0),
+      Decision::NoMatch => Some(10),
+      Decision::Cancel => Some(if password { 1 } else { 0 }),
+    }
+  }
+}
+
+// the key for a name in angle brackets, the names of the xkb keysyms and a few short forms
+fn named_key(name: &str) -> Option<Key> {
+  match name {
+    "Escape" | "Esc" => Some(Key::Escape),
+    "BackSpace" | "Backspace" | "BS" => Some(Key::BackSpace),
+    "Tab" => Some(Key::Tab),
+    "ISO_Left_Tab" | "S-Tab" => Some(Key::LeftTab),
+    "Return" | "Enter" | "CR" => Some(Key::Return),
+    "lt" => Some(Key::Text(String::from("<"))),
+    "gt" => Some(Key::Text(String::from(">"))),
+    "Space" | "space" => Some(Key::Text(String::from(" "))),
+    _ => None,
+  }
+}
+
+// splits `fir<Tab><Return>` into the single keys, line breaks are ignored so files can be wrapped
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>> {
+  let mut list = Vec::new();
+  let mut rest = keys;
+
+  while let Some(c) = rest.chars().next() {
+    if c == '<' {
+      let Some(end) = rest.find('>') else {
+        bail!(
+          "Unterminated key name in {:?}, use <lt> for a literal <",
+          rest
+        );
+      };
+      let name = &rest[1..end];
+      match named_key(name) {
+        Some(key) => list.push(key),
+        None => bail!("Unknown key <{}>", name),
+      }
+      rest = &rest[end + 1..];
+    } else {
+      if c != '\n' && c != '\r' {
+        list.push(Key::Text(c.to_string()));
+      }
+      rest = &rest[c.len_utf8()..];
+    }
+  }
+
+  Ok(list)
+}
+
+pub fn read_keys(path: &Path) -> Result<Vec<Key>> {
+  let source = std::fs::read_to_string(path)
+    .with_context(|| format!("Failed to read the keys {}", path.display()))?;
+  parse_keys(&source)
+}
+
+// a JSON string literal
+fn quote(text: &str) -> String {
+  let mut quoted = String::from("\"");
+  for c in text.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+// the final state of the menu after the replayed keys
+pub struct Report {
+  // None for a password, it must not be printed, so it also tells the password mode
+  input: Option<String>,
+  // the keys handled before the menu was closed
+  keys: usize,
+  filtered: usize,
+  index: usize,
+  selection: Option<Command>,
+  decision: Decision,
+}
+
+impl Report {
+  fn to_json(&self) -> String {
+    let selection = match &self.selection {
+      Some(command) => format!(
+        "{{\"name\":{},\"path\":{}}}",
+        quote(&command.name),
+        quote(&command.path.to_string_lossy())
+      ),
+      None => String::from("null"),
+    };
+    let exit_code = self
+      .decision
+      .exit_code(self.input.is_none())
+      .map_or_else(|| String::from("null"), |code| code.to_string());
+
+    format!(
+      "{{\"input\":{},\"keys\":{},\"filtered\":{},\"index\":{},\"selection\":{},\"decision\":{},\"exit_code\":{}}}",
+      self.input.as_deref().map_or_else(|| String::from("null"), quote),
+      self.keys,
+      self.filtered,
+      self.index,
+      selection,
+      quote(self.decision.name()),
+      exit_code
+    )
+  }
+}
+
+// feeds the keys through the same handling as the keyboard of the menu until it is closed,
+// nothing gets spawned and the usage statistic is left untouched
+pub fn replay(mut context: AppContext, keys: Vec<Key>) -> Report {
+  let mut decision = Decision::Pending;
+  let mut accepted = None;
+  let total = keys.len();
+  let mut handled = 0;
+
+  for key in keys {
+    handled += 1;
+    let Some(action) = Action::from_key(key) else {
+      continue;
+    };
+    match context.apply(action) {
+      Outcome::Unchanged | Outcome::Changed => continue,
+      Outcome::Accepted(command) => {
+        accepted = Some(command);
+        decision = Decision::Launch;
+      }
+      Outcome::Submitted(_) => decision = Decision::Submit,
+      Outcome::NoMatch => decision = Decision::NoMatch,
+      Outcome::Cancelled => decision = Decision::Cancel,
+    }
+    break;
+  }
+
+  if handled < total {
+    warn!("The menu closed with {} keys left", total - handled);
+  }
+  debug!("Replayed {} of {} keys", handled, total);
+
+  Report {
+    input: (!context.config().static_config.password()).then(|| String::from(context.input())),
+    keys: handled,
+    filtered: context.list().filtered_len(),
+    index: context.current_index(),
+    selection: accepted.or_else(|| context.target().cloned()),
+    decision,
+  }
+}
+
+// replays the keys on the entries from the file, or the binaries in PATH without one, and prints
+// the report as JSON
+pub fn run(config: AppConfig, entries: Option<&Path>, keys: Vec<Key>) -> Result<()> {
+  let context = match entries {
+    Some(path) => {
+      let list = CommandList::from_paths(&config, render::read_entries(path)?);
+      AppContext::with_list(config, list)
+    }
+    None => AppContext::new(config)?,
+  };
+
+  println!("{}", replay(context, keys).to_json());
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use rmenu::config::StaticConfig;
+
+  use super::*;
+
+  fn text(text: &str) -> Key {
+    Key::Text(String::from(text))
+  }
+
+  #[test]
+  fn parses_text_and_named_keys() {
+    assert_eq!(
+      parse_keys("a<Tab><lt>b<Return>").unwrap(),
+      [text("a"), Key::Tab, text("<"), text("b"), Key::Return]
+    );
+  }
+
+  #[test]
+  fn ignores_line_breaks() {
+    assert_eq!(
+      parse_keys("a\r\nb\n<BS>\n").unwrap(),
+      [text("a"), text("b"), Key::BackSpace]
+    );
+  }
+
+  #[test]
+  fn rejects_an_unterminated_key_name() {
+    let error = parse_keys("a<Tab").unwrap_err().to_string();
+    assert!(error.contains("Unterminated"), "{}", error);
+  }
+
+  #[test]
+  fn rejects_an_unknown_key_name() {
+    let error = parse_keys("<Foo>").unwrap_err().to_string();
+    assert_eq!(error, "Unknown key <Foo>");
+  }
+
+  #[test]
+  fn replays_the_keys_on_the_entries() {
+    let config = AppConfig::from(StaticConfig::default());
+    let entries = ["/bin/firefox", "/bin/fish", "/bin/vim"]
+      .iter()
+      .map(PathBuf::from)
+      .collect();
+    let list = CommandList::from_paths(&config, entries);
+    let context = AppContext::with_list(config, list);
+
+    let report = replay(context, parse_keys("fi<Tab><Return>x").unwrap());
+    assert_eq!(report.input.as_deref(), Some("fi"));
+    assert_eq!(report.keys, 4);
+    assert_eq!(report.filtered, 2);
+    assert_eq!(report.index, 1);
+    assert_eq!(
+      report.to_json(),
+      "{\"input\":\"fi\",\"keys\":4,\"filtered\":2,\"index\":1,\
+       \"selection\":{\"name\":\"fish\",\"path\":\"/bin/fish\"},\
+       \"decision\":\"launch\",\"exit_code\":null}"
+    );
+  }
+
+  #[test]
+  fn keeps_the_password_out_of_the_report() {
+    let config = AppConfig::from(StaticConfig {
+      password: Some(true),
+      ..Default::default()
+    });
+    let context = AppContext::new(config).unwrap();
+
+    let report = replay(context, parse_keys("secret<Return>").unwrap());
+    assert_eq!(report.input, None);
+    assert_eq!(
+      report.to_json(),
+      "{\"input\":null,\"keys\":7,\"filtered\":0,\"index\":0,\"selection\":null,\
+       \"decision\":\"submit\",\"exit_code\":0}"
+    );
+  }
+
+  #[test]
+  fn fails_on_a_cancelled_password() {
+    let config = AppConfig::from(StaticConfig {
+      password: Some(true),
+      ..Default::default()
+    });
+    let context = AppContext::new(config).unwrap();
+
+    let report = replay(context, parse_keys("sec<Escape>").unwrap());
+    assert_eq!(report.decision.name(), "cancel");
+    assert_eq!(report.decision.exit_code(report.input.is_none()), Some(1));
+  }
+}
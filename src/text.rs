@@ -0,0 +1,472 @@
+use std::{
+  collections::{HashMap, HashSet},
+  ffi::{CStr, OsStr},
+  os::unix::ffi::OsStrExt,
+  path::{Path, PathBuf},
+  ptr,
+  rc::Rc,
+  sync::Arc,
+};
+
+use font_kit::{
+  canvas::{Canvas, Format, RasterizationOptions},
+  family_name::FamilyName,
+  font::Font,
+  handle::Handle,
+  hinting::HintingOptions,
+  properties::Properties,
+  source::SystemSource,
+};
+use log::debug;
+use pathfinder_geometry::{transform2d::Transform2F, vector::vec2f};
+use raqote::Mask;
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo};
+
+// a font together with its raw data, which the shaper works on
+pub struct FontFace {
+  pub font: Font,
+  data: Arc<Vec<u8>>,
+  index: u32,
+}
+
+// the fonts to pick the glyphs from in order of priority, the last one is the last resort
+pub struct FontSet {
+  faces: Vec<FontFace>,
+  order: Vec<usize>,
+  // the installed fonts loaded so far by their file and index
+  system: HashSet<(PathBuf, u32)>,
+  uncovered: HashSet<char>,
+  // shaped labels by text, point size and spacing
+  layouts: HashMap<(String, u32, u32), Rc<ShapedText>>,
+  // rasterized glyphs by face, point size, glyph and horizontal subpixel offset
+  glyphs: HashMap<(usize, u32, u32, u32), Option<RasterizedGlyph>>,
+}
+
+// the coverage of a glyph relative to the pixel its pen position falls into
+pub struct RasterizedGlyph {
+  pub left: i32,
+  pub top: i32,
+  pub mask: Mask,
+}
+
+pub struct ShapedGlyph {
+  // index of the face in the FontSet, None for characters no font covers
+  pub face: Option<usize>,
+  pub id: u32,
+  // byte offset of the first character of the cluster in the text
+  pub cluster: usize,
+  pub x: f32,
+  pub y: f32,
+}
+
+pub struct ShapedText {
+  pub glyphs: Vec<ShapedGlyph>,
+  pub width: f32,
+}
+
+impl FontFace {
+  pub fn from_bytes(data: Arc<Vec<u8>>, index: u32) -> Option<Self> {
+    // make sure the shaper is able to parse it as well
+    rustybuzz::Face::from_slice(&data, index)?;
+    let font = Font::from_bytes(data.clone(), index).ok()?;
+    Some(Self { font, data, index })
+  }
+
+  pub fn from_path(path: &Path) -> Option<Self> {
+    std::fs::read(path)
+      .ok()
+      .and_then(|data| Self::from_bytes(Arc::new(data), 0))
+  }
+
+  pub fn from_handle(handle: Handle) -> Option<Self> {
+    match handle {
+      Handle::Path { path, font_index } => std::fs::read(path)
+        .ok()
+        .and_then(|data| Self::from_bytes(Arc::new(data), font_index)),
+      Handle::Memory { bytes, font_index } => Self::from_bytes(bytes, font_index),
+    }
+  }
+
+  // looks up the PostScript name first and the family name (including the generic families) second
+  pub fn by_name(name: &str) -> Option<Self> {
+    let source = SystemSource::new();
+
+    let family = match name.to_lowercase().as_str() {
+      "monospace" | "mono" => FamilyName::Monospace,
+      "sans-serif" | "sans" => FamilyName::SansSerif,
+      "serif" => FamilyName::Serif,
+      _ => FamilyName::Title(String::from(name)),
+    };
+
+    source
+      .select_by_postscript_name(name)
+      .or_else(|_| source.select_best_match(&[family], &Properties::new()))
+      .ok()
+      .and_then(Self::from_handle)
+  }
+
+  // a fallback entry is either a path to a font file or the name of an installed font
+  pub fn from_spec(spec: &str) -> Option<Self> {
+    let path = Path::new(spec);
+    if spec.contains('/') || path.exists() {
+      Self::from_path(path)
+    } else {
+      Self::by_name(spec)
+    }
+  }
+
+  pub fn has_glyph(&self, c: char) -> bool {
+    self.font.glyph_for_char(c).is_some()
+  }
+}
+
+// asks fontconfig for the installed font covering `c`, it answers from its cache without
+// opening any font file
+fn installed_font_for(c: char) -> Option<(PathBuf, u32)> {
+  use fontconfig_sys::{constants::*, *};
+
+  // SAFETY: every object created here is destroyed before returning and the file name is copied
+  // before the matched pattern owning it goes away
+  unsafe {
+    let pattern = FcPatternCreate();
+    let charset = FcCharSetCreate();
+    FcCharSetAddChar(charset, c as FcChar32);
+    // the pattern keeps its own reference to the charset
+    FcPatternAddCharSet(pattern, FC_CHARSET.as_ptr(), charset);
+    FcCharSetDestroy(charset);
+    FcConfigSubstitute(ptr::null_mut(), pattern, FcMatchPattern);
+    FcDefaultSubstitute(pattern);
+
+    let mut result = FcResultNoMatch;
+    let matched = FcFontMatch(ptr::null_mut(), pattern, &mut result);
+    FcPatternDestroy(pattern);
+    if matched.is_null() {
+      return None;
+    }
+
+    // the best match is not necessarily one that covers the character
+    let mut coverage = ptr::null_mut();
+    let mut file = ptr::null_mut();
+    let mut index = 0;
+    let covered = FcPatternGetCharSet(matched, FC_CHARSET.as_ptr(), 0, &mut coverage)
+      == FcResultMatch
+      && FcCharSetHasChar(coverage, c as FcChar32) != 0
+      && FcPatternGetString(matched, FC_FILE.as_ptr(), 0, &mut file) == FcResultMatch;
+    FcPatternGetInteger(matched, FC_INDEX.as_ptr(), 0, &mut index);
+
+    let font = covered.then(|| {
+      let file = CStr::from_ptr(file as *const std::os::raw::c_char);
+      (
+        PathBuf::from(OsStr::from_bytes(file.to_bytes())),
+        index.max(0) as u32,
+      )
+    });
+    FcPatternDestroy(matched);
+    font
+  }
+}
+
+// keeps track of the pen while shaping multiple segments into one line
+struct Pen {
+  glyphs: Vec<ShapedGlyph>,
+  x: f32,
+  spacing: f32,
+  cluster_spacing: f32,
+}
+
+impl Pen {
+  fn width(&self) -> f32 {
+    self.x + self.spacing + self.cluster_spacing
+  }
+
+  // the spacing is only added between clusters to keep combining marks on their base
+  fn next_cluster(&mut self) {
+    self.spacing += self.cluster_spacing;
+    self.cluster_spacing = 0.;
+  }
+}
+
+static TOFU_WIDTH: f32 = 0.6;
+
+// glyphs are rasterized at quarter pixel offsets
+pub static SUBPIXELS: u32 = 4;
+
+// labels come and go with the filter, so the layout cache is dropped once it grows this large
+static MAX_LAYOUTS: usize = 4096;
+
+impl FontSet {
+  // the last resort doubles as the primary font if none is given
+  pub fn new(
+    primary: Option<FontFace>,
+    fallback: Vec<FontFace>,
+    last_resort: Option<FontFace>,
+  ) -> Self {
+    let shared = primary.is_none() && last_resort.is_some();
+    let faces: Vec<FontFace> = primary
+      .into_iter()
+      .chain(fallback)
+      .chain(last_resort)
+      .collect();
+    let mut order: Vec<usize> = (0..faces.len()).collect();
+    if shared {
+      order.insert(0, faces.len() - 1);
+    }
+
+    Self {
+      order,
+      faces,
+      system: HashSet::new(),
+      uncovered: HashSet::new(),
+      layouts: HashMap::new(),
+      glyphs: HashMap::new(),
+    }
+  }
+
+  // the shaped text from the cache, shaped on the first use
+  pub fn layout(&mut self, text: &str, point_size: f32, space_factor: f32) -> Rc<ShapedText> {
+    let key = (
+      String::from(text),
+      point_size.to_bits(),
+      space_factor.to_bits(),
+    );
+    if let Some(shaped) = self.layouts.get(&key) {
+      return shaped.clone();
+    }
+
+    if self.layouts.len() >= MAX_LAYOUTS {
+      self.layouts.clear();
+    }
+
+    let shaped = Rc::new(self.shape(text, point_size, space_factor));
+    self.layouts.insert(key, shaped.clone());
+    shaped
+  }
+
+  // the glyph rasterized once for each subpixel offset, None for glyphs without an outline
+  pub fn rasterize(
+    &mut self,
+    face: usize,
+    id: u32,
+    point_size: f32,
+    subpixel: u32,
+  ) -> Option<&RasterizedGlyph> {
+    let faces = &self.faces;
+    self
+      .glyphs
+      .entry((face, point_size.to_bits(), id, subpixel))
+      .or_insert_with(|| {
+        let font = &faces[face].font;
+        let offset = Transform2F::from_translation(vec2f(subpixel as f32 / SUBPIXELS as f32, 0.));
+        let bounds = font
+          .raster_bounds(
+            id,
+            point_size,
+            offset,
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+          )
+          .ok()
+          .filter(|bounds| bounds.width() > 0 && bounds.height() > 0)?;
+
+        let mut canvas = Canvas::new(bounds.size(), Format::A8);
+        font
+          .rasterize_glyph(
+            &mut canvas,
+            id,
+            point_size,
+            Transform2F::from_translation(-bounds.origin().to_f32()) * offset,
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+          )
+          .ok()?;
+
+        Some(RasterizedGlyph {
+          left: bounds.origin_x(),
+          top: bounds.origin_y(),
+          mask: Mask {
+            width: bounds.width(),
+            height: bounds.height(),
+            data: canvas.pixels,
+          },
+        })
+      })
+      .as_ref()
+  }
+
+  // the face for `c`, marks stay with the face of their base character if possible
+  fn face_for(&mut self, c: char, previous: Option<usize>) -> Option<usize> {
+    if let Some(previous) = previous
+      .filter(|_| bidi_class(c) == BidiClass::NSM)
+      .filter(|p| self.faces[*p].has_glyph(c))
+    {
+      return Some(previous);
+    }
+
+    if self.order.is_empty() {
+      return None;
+    }
+
+    let last_resort = self.order.len() - 1;
+    if let Some(index) = self.order[..last_resort]
+      .iter()
+      .find(|index| self.faces[**index].has_glyph(c))
+    {
+      return Some(*index);
+    }
+
+    if let Some(index) = self.system_face_for(c) {
+      return Some(index);
+    }
+
+    Some(self.order[last_resort]).filter(|index| self.faces[*index].has_glyph(c))
+  }
+
+  // searches the installed fonts, found fonts are added right before the last resort
+  fn system_face_for(&mut self, c: char) -> Option<usize> {
+    if self.uncovered.contains(&c) {
+      return None;
+    }
+
+    // every installed font is loaded once at most, a font already loaded did not have the glyph
+    let face = installed_font_for(c)
+      .filter(|font| self.system.insert(font.clone()))
+      .and_then(|(path, font_index)| FontFace::from_handle(Handle::Path { path, font_index }))
+      .filter(|face| face.has_glyph(c));
+
+    match face {
+      Some(face) => {
+        debug!("Fallback to {:?} for {:?}", face.font.full_name(), c);
+        self.faces.push(face);
+        let index = self.faces.len() - 1;
+        self.order.insert(self.order.len() - 1, index);
+        Some(index)
+      }
+      None => {
+        self.uncovered.insert(c);
+        None
+      }
+    }
+  }
+
+  // shapes `text` in visual order, `space_factor` stretches the advance of every cluster
+  pub fn shape(&mut self, text: &str, point_size: f32, space_factor: f32) -> ShapedText {
+    let mut pen = Pen {
+      glyphs: Vec::new(),
+      x: 0.,
+      spacing: 0.,
+      cluster_spacing: 0.,
+    };
+
+    // the spacing is relative to the primary face, so fallback faces keep their proportions
+    let spacing = match self.order.first() {
+      Some(primary) => {
+        let upm = self.faces[*primary].font.metrics().units_per_em as f32;
+        space_factor * upm / 24. / 96.
+      }
+      None => 1.,
+    };
+
+    let bidi = BidiInfo::new(text, None);
+    for paragraph in &bidi.paragraphs {
+      let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+
+      for run in runs {
+        let direction = if levels[run.start].is_rtl() {
+          Direction::RightToLeft
+        } else {
+          Direction::LeftToRight
+        };
+
+        // split the run into segments that are covered by the same face
+        let mut segments: Vec<(Option<usize>, std::ops::Range<usize>)> = Vec::new();
+        for (offset, c) in text[run.clone()].char_indices() {
+          let start = run.start + offset;
+          let previous = segments.last().and_then(|(face, _)| *face);
+          let face = self.face_for(c, previous);
+          match segments.last_mut() {
+            Some((last, range)) if *last == face => range.end = start + c.len_utf8(),
+            _ => segments.push((face, start..start + c.len_utf8())),
+          }
+        }
+
+        if direction == Direction::RightToLeft {
+          segments.reverse();
+        }
+
+        for (face, range) in segments {
+          match face {
+            Some(face) => {
+              self.shape_segment(&mut pen, face, text, range, direction, point_size, spacing)
+            }
+            None => {
+              for (offset, _) in text[range.clone()].char_indices() {
+                pen.next_cluster();
+                pen.glyphs.push(ShapedGlyph {
+                  face: None,
+                  id: 0,
+                  cluster: range.start + offset,
+                  x: pen.x + pen.spacing,
+                  y: 0.,
+                });
+                pen.x += point_size * TOFU_WIDTH;
+              }
+            }
+          }
+        }
+      }
+    }
+
+    ShapedText {
+      width: pen.width(),
+      glyphs: pen.glyphs,
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn shape_segment(
+    &self,
+    pen: &mut Pen,
+    index: usize,
+    text: &str,
+    range: std::ops::Range<usize>,
+    direction: Direction,
+    point_size: f32,
+    spacing: f32,
+  ) {
+    let font = &self.faces[index];
+    let Some(face) = rustybuzz::Face::from_slice(&font.data, font.index) else {
+      return;
+    };
+
+    // the scale of the outlines and the stretched scale of the advances
+    let scale = point_size / face.units_per_em() as f32;
+    let advance_scale = scale * spacing;
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&text[range.clone()]);
+    buffer.guess_segment_properties();
+    buffer.set_direction(direction);
+
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    let mut cluster = None;
+    for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+      if cluster != Some(info.cluster) {
+        cluster = Some(info.cluster);
+        pen.next_cluster();
+      }
+
+      pen.glyphs.push(ShapedGlyph {
+        face: Some(index),
+        id: info.glyph_id,
+        cluster: range.start + info.cluster as usize,
+        x: pen.x + pen.spacing + position.x_offset as f32 * scale,
+        y: -position.y_offset as f32 * scale,
+      });
+
+      pen.x += position.x_advance as f32 * scale;
+      pen.cluster_spacing += position.x_advance as f32 * (advance_scale - scale);
+    }
+  }
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+use serde_yaml::Value;
+
+use crate::config::{self, Theme};
+
+static BUILTIN_THEMES: [(&str, &str); 4] = [
+  ("dark", include_str!("../assets/themes/dark.yaml")),
+  ("light", include_str!("../assets/themes/light.yaml")),
+  ("nord", include_str!("../assets/themes/nord.yaml")),
+  ("gruvbox", include_str!("../assets/themes/gruvbox.yaml")),
+];
+
+// a theme name must not lead out of the themes directory
+pub fn is_valid_name(name: &str) -> bool {
+  !name.is_empty() && !name.contains(['/', '\\']) && !name.contains("..")
+}
+
+// a theme file in one of the config directories takes precedence over a built-in one with the same name
+pub fn user_theme_path(name: &str) -> Option<PathBuf> {
+  if !is_valid_name(name) {
+    return None;
+  }
+  config::config_dirs()
+    .into_iter()
+    .map(|dir| dir.join("themes").join(format!("{}.yaml", name)))
+    .find(|path| path.exists())
+}
+
+pub fn builtin_theme(name: &str) -> Option<&'static str> {
+  BUILTIN_THEMES
+    .iter()
+    .find(|(builtin, _)| *builtin == name)
+    .map(|(_, source)| *source)
+}
+
+pub fn load(name: &str) -> Result<Value> {
+  if !is_valid_name(name) {
+    bail!("Invalid theme name `{}`, it must not contain a path", name);
+  }
+  if let Some(path) = user_theme_path(name) {
+    return config::load_layer::<Theme>(&path);
+  }
+
+  let source = builtin_theme(name).ok_or_else(|| {
+    anyhow!(
+      "Unknown theme `{}`, built-in themes are {}",
+      name,
+      BUILTIN_THEMES.map(|(name, _)| name).join(", ")
+    )
+  })?;
+
+  Ok(serde_yaml::from_str(source)?)
+}